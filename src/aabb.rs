@@ -0,0 +1,126 @@
+use crate::{
+    Ray,
+    Vec3,
+};
+
+type Point3 = Vec3;
+
+/// Axis-aligned bounding box, stored as its minimum and maximum corners
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: Point3,
+    pub max: Point3,
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        Aabb::empty()
+    }
+}
+
+impl Aabb {
+    /// Builds the box spanning the two points, they can be given in any order
+    pub fn new(a: Point3, b: Point3) -> Aabb {
+        let mut res = Aabb {
+            min: Point3::new(a.x().min(*b.x()), a.y().min(*b.y()), a.z().min(*b.z())),
+            max: Point3::new(a.x().max(*b.x()), a.y().max(*b.y()), a.z().max(*b.z())),
+        };
+
+        res.pad_to_minimums();
+
+        res
+    }
+
+    /// Box containing nothing, surrounding it with another box returns the
+    /// other box
+    pub fn empty() -> Aabb {
+        Aabb {
+            min: Point3::new(f64::INFINITY, f64::INFINITY, f64::INFINITY),
+            max: Point3::new(-f64::INFINITY, -f64::INFINITY, -f64::INFINITY),
+        }
+    }
+
+    /// Smallest box containing both boxes
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(a.min.x().min(*b.min.x()), a.min.y().min(*b.min.y()), a.min.z().min(*b.min.z())),
+            max: Point3::new(a.max.x().max(*b.max.x()), a.max.y().max(*b.max.y()), a.max.z().max(*b.max.z())),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn surface_area(&self) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+
+        let extent = self.max - self.min;
+
+        2. * (extent.x() * extent.y() + extent.y() * extent.z() + extent.z() * extent.x())
+    }
+
+    /// Index of the axis the box is longest along, 0 -> x, 1 -> y, 2 -> z
+    pub fn longest_axis(&self) -> usize {
+        let extent = self.max - self.min;
+
+        if extent.x() > extent.y() {
+            if extent.x() > extent.z() { 0 } else { 2 }
+        } else if extent.y() > extent.z() {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Slab test, checks if the ray passes through the box anywhere in
+    /// [ray_tmin, ray_tmax]
+    pub fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> bool {
+        let mut tmin = ray_tmin;
+        let mut tmax = ray_tmax;
+
+        for axis in 0..3 {
+            let inv_d = 1. / r.direction[axis];
+
+            let mut t0 = (self.min[axis] - r.origin[axis]) * inv_d;
+            let mut t1 = (self.max[axis] - r.origin[axis]) * inv_d;
+
+            // ray travelling in negative direction along this axis
+            if inv_d < 0. {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+
+            if t0 > tmin {
+                tmin = t0;
+            }
+            if t1 < tmax {
+                tmax = t1;
+            }
+
+            if tmax <= tmin {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    /// Flat shapes give a box with no thickness along one axis which rays
+    /// parallel to it would never hit, so give every side a small width
+    fn pad_to_minimums(&mut self) {
+        let delta = 0.0001;
+
+        for axis in 0..3 {
+            if self.max[axis] - self.min[axis] < delta {
+                self.min[axis] -= delta / 2.;
+                self.max[axis] += delta / 2.;
+            }
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::{
+    create_lerp_func,
+    environment::EnvironmentMap,
+    Color,
+    Ray,
+};
+
+/// Color seen by rays that miss every object
+pub enum Background {
+    Solid(Color),
+    /// Vertical blend from create_lerp_func
+    Gradient(Box<dyn Fn(&Ray) -> Color + Send + Sync>),
+    Custom(Box<dyn Fn(&Ray) -> Color + Send + Sync>),
+    /// Image based lighting from a panorama of the surroundings
+    Environment(EnvironmentMap),
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::gradient(Color::new(1, 1, 1), Color::new(1, 0.5, 0.7))
+    }
+}
+
+impl Background {
+    /// Blends from bottom for rays pointing straight down to top for rays
+    /// pointing straight up
+    pub fn gradient(bottom: Color, top: Color) -> Background {
+        Background::Gradient(create_lerp_func(bottom, top))
+    }
+
+    pub fn custom<F>(f: F) -> Background
+    where
+        F: Fn(&Ray) -> Color + Send + Sync + 'static,
+    {
+        Background::Custom(Box::from(f))
+    }
+
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Background::Solid(color) => *color,
+            Background::Gradient(f) | Background::Custom(f) => f(r),
+            Background::Environment(map) => map.color(&r.direction),
+        }
+    }
+}
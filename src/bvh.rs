@@ -0,0 +1,535 @@
+use std::cmp::Ordering;
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable, HittableList},
+    Color,
+    Ray,
+    Vec3,
+};
+
+type Point3 = Vec3;
+
+/// Bounding volume hierarchy node, each node splits its objects in two halves
+/// so a ray only has to test the objects whose boxes it passes through
+pub struct BvhNode {
+    left: Rc<dyn Hittable>,
+    right: Rc<dyn Hittable>,
+    bbox: Aabb,
+    chromatic_volume: bool,
+}
+
+impl BvhNode {
+    pub fn new(list: HittableList) -> BvhNode {
+        let mut objects = list.objects;
+
+        BvhNode::from_objects(&mut objects)
+    }
+
+    /// Reorders the slice while building, panics if it is empty
+    pub fn from_objects(objects: &mut [Rc<dyn Hittable>]) -> BvhNode {
+        assert!(!objects.is_empty(), "cannot build a bvh from no objects");
+
+        let bbox = objects
+            .iter()
+            .fold(Aabb::empty(), |bbox, object| Aabb::surrounding(&bbox, &object.bounding_box()));
+
+        // splitting along the longest axis keeps child boxes from overlapping
+        // as much as possible
+        let axis = bbox.longest_axis();
+
+        let (left, right): (Rc<dyn Hittable>, Rc<dyn Hittable>) = match objects.len() {
+            1 => (objects[0].clone(), objects[0].clone()),
+            2 => (objects[0].clone(), objects[1].clone()),
+            len => {
+                objects.sort_by(|a, b| box_compare(a, b, axis));
+
+                let (lower, upper) = objects.split_at_mut(len / 2);
+
+                (
+                    Rc::from(BvhNode::from_objects(lower)),
+                    Rc::from(BvhNode::from_objects(upper)),
+                )
+            }
+        };
+
+        let chromatic_volume = left.has_chromatic_volume() || right.has_chromatic_volume();
+
+        BvhNode {
+            left,
+            right,
+            bbox,
+            chromatic_volume,
+        }
+    }
+}
+
+fn box_compare(a: &Rc<dyn Hittable>, b: &Rc<dyn Hittable>, axis: usize) -> Ordering {
+    let a_min = a.bounding_box().min[axis];
+    let b_min = b.bounding_box().min[axis];
+
+    a_min.total_cmp(&b_min)
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        if !self.bbox.hit(r, ray_tmin, ray_tmax) {
+            return None;
+        }
+
+        let left_hit = self.left.hit(r, ray_tmin, ray_tmax);
+
+        // only need to check right side for hits closer than left one
+        let closest_t_so_far = match &left_hit {
+            Some(record) => record.t,
+            None => ray_tmax,
+        };
+
+        match self.right.hit(r, ray_tmin, closest_t_so_far) {
+            Some(record) => Some(record),
+            None => left_hit,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        if !self.bbox.hit(r, ray_tmin, ray_tmax) {
+            return Color::new(1, 1, 1);
+        }
+
+        let left = self.left.transmittance(r, ray_tmin, ray_tmax);
+
+        // a leaf holds its one object on both sides
+        if left.length_squared() <= 0. || Rc::ptr_eq(&self.left, &self.right) {
+            return left;
+        }
+
+        left * self.right.transmittance(r, ray_tmin, ray_tmax)
+    }
+
+    fn transmittance_correction(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        if !self.chromatic_volume || !self.bbox.hit(r, ray_tmin, ray_tmax) {
+            return Color::new(1, 1, 1);
+        }
+
+        let left = self.left.transmittance_correction(r, ray_tmin, ray_tmax);
+
+        if Rc::ptr_eq(&self.left, &self.right) {
+            return left;
+        }
+
+        left * self.right.transmittance_correction(r, ray_tmin, ray_tmax)
+    }
+
+    fn has_chromatic_volume(&self) -> bool {
+        self.chromatic_volume
+    }
+}
+
+/// How a flat bvh decides where to split a set of objects
+#[derive(Clone, Copy)]
+pub enum SplitMethod {
+    /// Splits at the middle of the object centers along the longest axis
+    Middle,
+    /// Surface area heuristic, picks the split with the cheapest estimated
+    /// traversal cost out of a fixed number of bins
+    Sah,
+}
+
+/// Objects per leaf before the sah will always try to split
+const MAX_LEAF_OBJECTS: usize = 4;
+const SAH_BINS: usize = 12;
+/// Past this depth nodes are split into equal halves, keeping the tree shallow
+/// enough for the fixed size traversal stack
+const MAX_SPLIT_DEPTH: usize = 32;
+const TRAVERSAL_STACK_SIZE: usize = 64;
+
+/// Node of a flat bvh. Interior nodes are followed directly by their first
+/// child, offset gives the index of the second child. Leaves instead use
+/// offset as the index of their first object
+pub(crate) struct FlatBvhNode {
+    bbox: Aabb,
+    offset: usize,
+    /// Number of objects in a leaf, 0 for interior nodes
+    count: usize,
+    axis: usize,
+}
+
+/// Bounding volume hierarchy flattened into a contiguous array of nodes in
+/// depth first order, traversed with a stack instead of recursion
+pub struct FlatBvh {
+    nodes: Vec<FlatBvhNode>,
+    objects: Vec<Rc<dyn Hittable>>,
+    chromatic_volume: bool,
+}
+
+struct BuildObject {
+    index: usize,
+    bbox: Aabb,
+    centroid: Point3,
+}
+
+impl FlatBvh {
+    pub fn new(list: HittableList, split_method: SplitMethod) -> FlatBvh {
+        let boxes: Vec<Aabb> = list.objects.iter().map(|object| object.bounding_box()).collect();
+        let (nodes, order) = build_flat_nodes(&boxes, split_method);
+
+        // reorder so every leaf refers to a contiguous run of objects
+        let objects: Vec<Rc<dyn Hittable>> = order.iter().map(|&i| list.objects[i].clone()).collect();
+        let chromatic_volume = objects.iter().any(|object| object.has_chromatic_volume());
+
+        FlatBvh {
+            nodes,
+            objects,
+            chromatic_volume,
+        }
+    }
+}
+
+impl Hittable for FlatBvh {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        traverse_flat_nodes(&self.nodes, r, ray_tmin, ray_tmax, |i, tmax| {
+            self.objects[i].hit(r, ray_tmin, tmax)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        flat_nodes_bounding_box(&self.nodes)
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let mut res = Color::new(1, 1, 1);
+
+        visit_flat_nodes(&self.nodes, r, ray_tmin, ray_tmax, |i| {
+            res *= self.objects[i].transmittance(r, ray_tmin, ray_tmax);
+
+            res.length_squared() > 0.
+        });
+
+        res
+    }
+
+    fn transmittance_correction(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let mut res = Color::new(1, 1, 1);
+
+        if self.chromatic_volume {
+            visit_flat_nodes(&self.nodes, r, ray_tmin, ray_tmax, |i| {
+                res *= self.objects[i].transmittance_correction(r, ray_tmin, ray_tmax);
+
+                true
+            });
+        }
+
+        res
+    }
+
+    fn has_chromatic_volume(&self) -> bool {
+        self.chromatic_volume
+    }
+}
+
+/// Builds the flattened nodes over the given boxes, returns the nodes and the
+/// order the boxes have to be stored in for the leaf offsets to line up
+pub(crate) fn build_flat_nodes(boxes: &[Aabb], split_method: SplitMethod) -> (Vec<FlatBvhNode>, Vec<usize>) {
+    let mut build_objects: Vec<BuildObject> = boxes
+        .iter()
+        .enumerate()
+        .map(|(index, bbox)| BuildObject {
+            index,
+            bbox: *bbox,
+            centroid: bbox.centroid(),
+        })
+        .collect();
+
+    let mut nodes = Vec::with_capacity(2 * boxes.len());
+    let mut order = Vec::with_capacity(boxes.len());
+
+    if !build_objects.is_empty() {
+        build_recursive(&mut build_objects, split_method, 0, &mut nodes, &mut order);
+    }
+
+    (nodes, order)
+}
+
+/// Box of the root node, which encloses everything in the tree
+pub(crate) fn flat_nodes_bounding_box(nodes: &[FlatBvhNode]) -> Aabb {
+    match nodes.first() {
+        Some(root) => root.bbox,
+        None => Aabb::empty(),
+    }
+}
+
+/// Adds the node for these objects and all of its children, returns the index
+/// of the added node
+fn build_recursive(
+    objects: &mut [BuildObject],
+    split_method: SplitMethod,
+    depth: usize,
+    nodes: &mut Vec<FlatBvhNode>,
+    order: &mut Vec<usize>,
+) -> usize {
+    let bbox = objects
+        .iter()
+        .fold(Aabb::empty(), |bbox, object| Aabb::surrounding(&bbox, &object.bbox));
+    let centroid_bounds = objects
+        .iter()
+        .fold(Aabb::empty(), |bbox, object| {
+            // built directly as Aabb::new would pad the zero sized box
+            Aabb::surrounding(&bbox, &Aabb { min: object.centroid, max: object.centroid })
+        });
+
+    let node_index = nodes.len();
+    nodes.push(FlatBvhNode {
+        bbox,
+        offset: 0,
+        count: 0,
+        axis: 0,
+    });
+
+    let axis = centroid_bounds.longest_axis();
+    let centroid_extent = centroid_bounds.max[axis] - centroid_bounds.min[axis];
+
+    // can't separate objects that all share a center
+    let split = if objects.len() == 1 || centroid_extent <= 0. {
+        None
+    } else if depth >= MAX_SPLIT_DEPTH || objects.len() <= 2 {
+        Some(split_equal_counts(objects, axis))
+    } else {
+        match split_method {
+            SplitMethod::Middle => {
+                let mid_point = centroid_bounds.centroid()[axis];
+                let mid = partition(objects, |object| object.centroid[axis] < mid_point);
+
+                // rounding can leave every object on one side
+                if mid == 0 || mid == objects.len() {
+                    Some(split_equal_counts(objects, axis))
+                } else {
+                    Some(mid)
+                }
+            }
+            SplitMethod::Sah => split_sah(objects, &bbox, &centroid_bounds, axis),
+        }
+    };
+
+    match split {
+        None => {
+            nodes[node_index].offset = order.len();
+            nodes[node_index].count = objects.len();
+            order.extend(objects.iter().map(|object| object.index));
+        }
+        Some(mid) => {
+            let (lower, upper) = objects.split_at_mut(mid);
+
+            build_recursive(lower, split_method, depth + 1, nodes, order);
+            let second_child = build_recursive(upper, split_method, depth + 1, nodes, order);
+
+            nodes[node_index].offset = second_child;
+            nodes[node_index].axis = axis;
+        }
+    }
+
+    node_index
+}
+
+fn split_equal_counts(objects: &mut [BuildObject], axis: usize) -> usize {
+    let mid = objects.len() / 2;
+
+    objects.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+
+    mid
+}
+
+/// Bins objects by center and picks the bin boundary with the lowest cost,
+/// returns None if a leaf is cheaper than any split
+fn split_sah(
+    objects: &mut [BuildObject],
+    bbox: &Aabb,
+    centroid_bounds: &Aabb,
+    axis: usize,
+) -> Option<usize> {
+    let axis_min = centroid_bounds.min[axis];
+    let axis_extent = centroid_bounds.max[axis] - axis_min;
+
+    let bin_of = |object: &BuildObject| {
+        let bin = (SAH_BINS as f64 * (object.centroid[axis] - axis_min) / axis_extent) as usize;
+        bin.min(SAH_BINS - 1)
+    };
+
+    let mut bin_counts = [0usize; SAH_BINS];
+    let mut bin_boxes = [Aabb::empty(); SAH_BINS];
+
+    for object in objects.iter() {
+        let bin = bin_of(object);
+        bin_counts[bin] += 1;
+        bin_boxes[bin] = Aabb::surrounding(&bin_boxes[bin], &object.bbox);
+    }
+
+    // sweep from both ends so each split's cost takes constant time
+    let mut below_areas = [0.; SAH_BINS - 1];
+    let mut below_counts = [0usize; SAH_BINS - 1];
+    let mut running_box = Aabb::empty();
+    let mut running_count = 0;
+    for split in 0..SAH_BINS - 1 {
+        running_box = Aabb::surrounding(&running_box, &bin_boxes[split]);
+        running_count += bin_counts[split];
+        below_areas[split] = running_box.surface_area();
+        below_counts[split] = running_count;
+    }
+
+    let mut costs = [0.; SAH_BINS - 1];
+    let mut running_box = Aabb::empty();
+    let mut running_count = 0;
+    for split in (0..SAH_BINS - 1).rev() {
+        running_box = Aabb::surrounding(&running_box, &bin_boxes[split + 1]);
+        running_count += bin_counts[split + 1];
+        costs[split] = below_counts[split] as f64 * below_areas[split]
+            + running_count as f64 * running_box.surface_area();
+    }
+
+    let (best_split, best_cost) = costs
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .map(|(split, cost)| (split, *cost))
+        .unwrap();
+
+    // cost relative to testing every object, with traversing a node taking
+    // an eighth of the time of an intersection test
+    let split_cost = 0.125 + best_cost / bbox.surface_area();
+    let leaf_cost = objects.len() as f64;
+
+    if objects.len() <= MAX_LEAF_OBJECTS && split_cost >= leaf_cost {
+        return None;
+    }
+
+    let mid = partition(objects, |object| bin_of(object) <= best_split);
+
+    // every object landed on one side, fall back to splitting the count
+    if mid == 0 || mid == objects.len() {
+        return Some(split_equal_counts(objects, axis));
+    }
+
+    Some(mid)
+}
+
+/// Moves the objects matching pred to the front, returns how many there are
+fn partition(objects: &mut [BuildObject], pred: impl Fn(&BuildObject) -> bool) -> usize {
+    let mut mid = 0;
+
+    for i in 0..objects.len() {
+        if pred(&objects[i]) {
+            objects.swap(i, mid);
+            mid += 1;
+        }
+    }
+
+    mid
+}
+
+/// Walks the nodes front to back, calling hit_object with each object index
+/// in a leaf the ray reaches and the closest hit distance found so far
+pub(crate) fn traverse_flat_nodes<F>(
+    nodes: &[FlatBvhNode],
+    r: &Ray,
+    ray_tmin: f64,
+    ray_tmax: f64,
+    mut hit_object: F,
+) -> Option<HitRecord>
+where
+    F: FnMut(usize, f64) -> Option<HitRecord>,
+{
+    if nodes.is_empty() {
+        return None;
+    }
+
+    let dir_is_neg = [r.direction[0] < 0., r.direction[1] < 0., r.direction[2] < 0.];
+
+    let mut hit_record: Option<HitRecord> = None;
+    let mut closest_t_so_far = ray_tmax;
+
+    let mut to_visit = [0usize; TRAVERSAL_STACK_SIZE];
+    let mut to_visit_len = 0;
+    let mut current = 0;
+
+    loop {
+        let node = &nodes[current];
+
+        if node.bbox.hit(r, ray_tmin, closest_t_so_far) {
+            if node.count > 0 {
+                for i in node.offset..node.offset + node.count {
+                    if let Some(record) = hit_object(i, closest_t_so_far) {
+                        closest_t_so_far = record.t;
+                        hit_record = Some(record);
+                    }
+                }
+            } else {
+                // visit the child nearer the ray origin first so the far one
+                // can be culled by a closer hit
+                let (near, far) = if dir_is_neg[node.axis] {
+                    (node.offset, current + 1)
+                } else {
+                    (current + 1, node.offset)
+                };
+
+                to_visit[to_visit_len] = far;
+                to_visit_len += 1;
+                current = near;
+                continue;
+            }
+        }
+
+        if to_visit_len == 0 {
+            break;
+        }
+
+        to_visit_len -= 1;
+        current = to_visit[to_visit_len];
+    }
+
+    hit_record
+}
+
+/// Calls visit with every object whose leaf box r passes through between
+/// ray_tmin and ray_tmax, until it returns false
+fn visit_flat_nodes<F>(nodes: &[FlatBvhNode], r: &Ray, ray_tmin: f64, ray_tmax: f64, mut visit: F)
+where
+    F: FnMut(usize) -> bool,
+{
+    if nodes.is_empty() {
+        return;
+    }
+
+    let mut to_visit = [0usize; TRAVERSAL_STACK_SIZE];
+    let mut to_visit_len = 0;
+    let mut current = 0;
+
+    loop {
+        let node = &nodes[current];
+
+        if node.bbox.hit(r, ray_tmin, ray_tmax) {
+            if node.count > 0 {
+                for i in node.offset..node.offset + node.count {
+                    if !visit(i) {
+                        return;
+                    }
+                }
+            } else {
+                to_visit[to_visit_len] = node.offset;
+                to_visit_len += 1;
+                current += 1;
+                continue;
+            }
+        }
+
+        if to_visit_len == 0 {
+            break;
+        }
+
+        to_visit_len -= 1;
+        current = to_visit[to_visit_len];
+    }
+}
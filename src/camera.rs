@@ -1,12 +1,19 @@
 use indicatif::ProgressBar;
-use std::sync::{Arc, Mutex};
-use std::{fs, cell::RefCell};
+use std::sync::{mpsc, Arc};
+use std::{path::Path, cell::RefCell};
 
 use crate::{
-    create_lerp_func,
+    background::Background,
     degrees_to_radians,
+    film::{Film, FilmPixel},
+    hit::{HitRecord, HittableList},
+    light::{power_heuristic, LightSampler},
+    material::ScatterKind,
+    pdf::Pdf,
+    output::{writer_for_path, ImageError, OutputOptions},
     pool::ThreadPool,
     random_f64,
+    spectrum::{self, ColorMode, PathColor, Wavelengths, WAVELENGTH_COUNT},
     Color,
     Hittable,
     Point3,
@@ -27,13 +34,22 @@ pub struct Camera {
     pub look_at: Point3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    /// Each ray is sent at a random time between the shutter opening and
+    /// closing, moving objects blur over the interval
+    pub shutter_open: f64,
+    pub shutter_close: f64,
+    /// Color of rays that miss everything
+    pub background: Background,
+    /// Whether paths carry rgb or a single wavelength
+    pub color_mode: ColorMode,
+    /// Settings for image formats that can be written more than one way
+    pub output: OutputOptions,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
     image_height: u32,
     px00_loc: Point3,
     pixel_delta_u: Vec3,
     pixel_delta_v: Vec3,
-    pixel_samples_scale: f32,
     u: Vec3,
     v: Vec3,
     w: Vec3,
@@ -55,13 +71,17 @@ impl Default for Camera {
             px00_loc: Point3::new(0, 0, 0),
             pixel_delta_u: Vec3::new(0, 0, 0),
             pixel_delta_v: Vec3::new(0, 0, 0),
-            pixel_samples_scale: 0.,
             max_ray_bounce_depth: 10,
             u: Vec3::default(),
             v: Vec3::default(),
             w: Vec3::default(),
             defocus_angle: 0.,
             focus_dist: 10.,
+            shutter_open: 0.,
+            shutter_close: 1.,
+            background: Background::default(),
+            color_mode: ColorMode::default(),
+            output: OutputOptions::default(),
             defocus_disk_u: Vec3::default(),
             defocus_disk_v: Vec3::default(),
         }
@@ -99,8 +119,17 @@ impl Camera {
         };
 
         let ray_dir = pixel_sample - ray_origin;
+        let ray_time = self.shutter_open + random_f64() * (self.shutter_close - self.shutter_open);
 
-        Ray::new(ray_origin, ray_dir)
+        let mut r = Ray::new(ray_origin, ray_dir, ray_time);
+
+        r.color = match self.color_mode {
+            ColorMode::Rgb => PathColor::Rgb,
+            ColorMode::SingleWavelength => PathColor::Single(spectrum::sample_wavelength()),
+            ColorMode::Spectral => PathColor::Spectral(Wavelengths::sample()),
+        };
+
+        r
     }
     
     pub fn initialize(&mut self) {
@@ -160,10 +189,30 @@ impl Camera {
         self.px00_loc = pixel00_loc;
         self.pixel_delta_u = pixel_delta_u;
         self.pixel_delta_v = pixel_delta_v;
-        self.pixel_samples_scale = 1. / self.samples_per_pixel as f32;
     }
 
-    pub fn render(&mut self, world: &dyn Hittable, image_path: &str) {
+    /// Renders the world and writes it to image_path, the format is picked
+    /// by the path's extension. lights are sampled directly at every diffuse
+    /// hit, usually from HittableList::lights
+    pub fn render<P: AsRef<Path>>(
+        &mut self,
+        world: &dyn Hittable,
+        lights: &HittableList,
+        image_path: P,
+    ) -> Result<Film, ImageError> {
+        let image_path = image_path.as_ref();
+
+        // fail on an unsupported format before spending time rendering
+        let writer = writer_for_path(image_path, self.output)?;
+
+        let film = self.render_to_image(world, lights);
+        writer.write(&film, image_path)?;
+
+        Ok(film)
+    }
+
+    /// Renders the world without writing it anywhere
+    pub fn render_to_image(&mut self, world: &dyn Hittable, lights: &HittableList) -> Film {
         self.initialize();
 
         println!("Generating {} by {} image...", self.image_width, self.image_height);
@@ -172,79 +221,90 @@ impl Camera {
         log::info!("Scanlines remaining: ");
 
         let pool = ThreadPool::new(10);
-        let mut pixel_colors = Arc::new(Mutex::new(
-                vec![String::new(); (self.image_height * self.image_width) as usize]
-                ));
+        let (sender, receiver) = mpsc::channel();
 
         let world: Arc<&dyn Hittable> = Arc::new(world);
-        let image_width = Arc::new(self.image_width);
-        let cam_arc = Arc::new(&mut *self);
+        let lights = Arc::new(lights);
+        let cam_arc = Arc::new(&*self);
 
+        // each job renders a whole row and sends it back, so workers never
+        // wait on each other to store their pixels
         for y in 0..cam_arc.image_height {
-            for x in 0..cam_arc.image_width {
-                bar.inc(1);
-
-                let world = Arc::clone(&world);
-                let pixel_colors: Arc<Mutex<Vec<String>>> = Arc::clone(&pixel_colors);
-                let i: u32 = x.clone();
-                let j: u32 = y.clone();
-                let image_width = Arc::clone(&image_width);
-                let cam = cam_arc.clone();
-
-                pool.execute(move || {
-                    let color = get_pixel_color(cam, i, j, world);
-                    let mut colors_array = pixel_colors.lock().unwrap();
-                    let idx: usize = (j * *image_width + i).try_into().unwrap();
-
-                    colors_array[idx] = color.get_color_256();
-                });
-            }
+            let world = Arc::clone(&world);
+            let lights = Arc::clone(&lights);
+            let sender = sender.clone();
+            let cam = cam_arc.clone();
+
+            pool.execute(move || {
+                let row: Vec<FilmPixel> = (0..cam.image_width)
+                    .map(|x| get_pixel(&cam, x, y, &world, &lights))
+                    .collect();
+
+                sender.send((y, row)).unwrap();
+            });
         }
 
-        log::info!("\rDone.                     \r");
-        bar.finish();
-
-        let mut res = String::new();
+        // receiving stops once the jobs have dropped every other sender
+        drop(sender);
 
-        res.push_str(&format!("P3\n{} {}\n255\n", cam_arc.image_width, cam_arc.image_height));
+        let mut film = Film::new(cam_arc.image_width, cam_arc.image_height);
 
-        for color in pixel_colors.lock().unwrap().iter() {
-            res.push_str(&(color.to_owned() + "\n"));
+        for (y, row) in receiver {
+            film.set_row(y, &row);
+            bar.inc(cam_arc.image_width as u64);
         }
 
-        fs::write(image_path, res).expect("Unable to write to file");
+        log::info!("\rDone.                     \r");
+        bar.finish();
+
+        film
     }
 }
 
-fn get_pixel_color(
-    cam: Arc<&mut Camera>,
+fn get_pixel(
+    cam: &Camera,
     x: u32,
     y: u32,
-    world: Arc<&dyn Hittable>
-    ) -> Color {
+    world: &Arc<&dyn Hittable>,
+    lights: &HittableList,
+    ) -> FilmPixel {
 
-    let mut pixel_color = Color::new(0, 0, 0);
+    let mut pixel = FilmPixel::default();
+    let lights = LightSampler::new(lights, &cam.background);
 
-    // color will become total sum of all samples and then
-    // be scaled down
     for _sample in 0..cam.samples_per_pixel {
         let r = cam.get_ray(x, y);
-        pixel_color += ray_color(
+        let mut color = ray_color(
             &r,
-            &world,
+            world,
+            &lights,
+            cam,
             cam.max_ray_bounce_depth,
+            None,
         );
-    }
 
-    pixel_color *= cam.pixel_samples_scale as f64;
+        match r.color {
+            PathColor::Rgb => (),
+            // a single wavelength only adds its own share to each channel
+            PathColor::Single(wavelength) => color *= spectrum::wavelength_to_rgb(wavelength),
+            PathColor::Spectral(wavelengths) => color = wavelengths.to_rgb(&color),
+        }
+
+        pixel.add_sample(color, 1.);
+    }
 
-    pixel_color
+    pixel
 }
 
+/// scatter_pdf is the density the previous bounce picked r's direction with,
+/// None when light sampling there was impossible
 fn ray_color(
     r: &Ray,
     world: &Arc<&dyn Hittable>,
+    lights: &LightSampler,
+    cam: &Camera,
     depth: u32,
+    scatter_pdf: Option<f64>,
 )
     -> Color
 {
@@ -254,21 +314,142 @@ fn ray_color(
             return Color::new(0, 0, 0);
         }
 
-        // if ray collides with an object in hittable world, return color
-        if let Some(rec) = world.hit(r, 0.001, INFINITY) {
+        // volumes that tracked a single channel in hit weigh the others for
+        // the stretch the ray got through, and glass the ray is inside tints
+        // it by distance
+        let Some(rec) = world.hit(r, 0.001, INFINITY) else {
+            return background_color(r, lights, cam, scatter_pdf)
+                * world.transmittance_correction(r, 0.001, INFINITY)
+                * r.interior_transmittance(INFINITY);
+        };
+
+        hit_color(r, &rec, world, lights, cam, depth, scatter_pdf)
+            * world.transmittance_correction(r, 0.001, rec.t)
+            * r.interior_transmittance(rec.t)
+    }
 
-            if let Some((scattered, attenuation)) = (*rec.material).scatter(r, &rec) {
-                return ray_color(&scattered, world, depth - 1)
-                    * attenuation;
+/// Light leaving rec back along r
+fn hit_color(
+    r: &Ray,
+    rec: &HitRecord,
+    world: &Arc<&dyn Hittable>,
+    lights: &LightSampler,
+    cam: &Camera,
+    depth: u32,
+    scatter_pdf: Option<f64>,
+) -> Color {
+    let mut emitted = match r.color.wavelengths() {
+        Some(wavelengths) => (*rec.material).emitted_spectrum(r, rec, wavelengths),
+        None => (*rec.material).emitted(r, rec),
+    };
+
+    // light sampling at the previous bounce may have found this light
+    // too, weight the two against each other
+    if let Some(scatter_pdf) = scatter_pdf {
+        let light_pdf = lights.hit_pdf(r, rec.t);
+        if light_pdf > 0. {
+            emitted *= power_heuristic(scatter_pdf, light_pdf);
+        }
+    }
+
+    let Some(srec) = (*rec.material).scatter(r, rec) else {
+        return emitted;
+    };
+
+    let attenuation = if rec.material.spectral_attenuation() {
+        srec.attenuation
+    } else {
+        path_color(&srec.attenuation, r)
+    };
+
+    let pdf = match srec.kind {
+        // specular bounces only go one way, so a sampled light can't
+        // be reached from here
+        ScatterKind::Specular(scattered) => {
+            let mut attenuation = attenuation;
+
+            // only the hero wavelength carries on, standing in for
+            // the others
+            let secondary_lost = |r: &Ray| r.color.wavelengths().is_some_and(|w| !w.secondary);
+            if secondary_lost(&scattered) && !secondary_lost(r) {
+                attenuation *= Color::new(WAVELENGTH_COUNT as f64, 0, 0);
+            }
+
+            return emitted + ray_color(&scattered, world, lights, cam, depth - 1, None) * attenuation;
+        }
+        ScatterKind::Diffuse(pdf) => pdf,
+    };
+
+    let direct = direct_light(r, rec, attenuation, pdf.as_ref(), world, lights);
+
+    let scattered = Ray::new(rec.point, pdf.generate(), r.time).with_path_of(r);
+    let pdf_value = pdf.value(&scattered.direction);
+
+    if pdf_value <= 0. {
+        return emitted + direct;
+    }
+
+    let scattering_pdf = (*rec.material).scattering_pdf(r, rec, &scattered);
+    let indirect = ray_color(&scattered, world, lights, cam, depth - 1, Some(pdf_value))
+        * attenuation
+        * (scattering_pdf / pdf_value);
+
+    emitted + direct + indirect
+}
+
+/// Light from the background along r, which hit nothing
+fn background_color(r: &Ray, lights: &LightSampler, cam: &Camera, scatter_pdf: Option<f64>) -> Color {
+    let background = path_color(&cam.background.color(r), r);
+
+    match scatter_pdf {
+        Some(scatter_pdf) => {
+            let light_pdf = lights.environment_pdf(&r.direction);
+            if light_pdf > 0. {
+                background * power_heuristic(scatter_pdf, light_pdf)
             } else {
-                return Color::new(0, 0, 0);
+                background
             }
         }
+        None => background,
+    }
+}
 
-        let start_color = Color::new(1, 1, 1);
-        let end_color = Color::new(1, 0.5, 0.7);
+/// color as a spectrum at r's wavelengths on a spectral path, as it is
+/// otherwise
+fn path_color(color: &Color, r: &Ray) -> Color {
+    match r.color.wavelengths() {
+        Some(wavelengths) => spectrum::rgb_to_spectrum(color, wavelengths),
+        None => *color,
+    }
+}
 
-        let unit_direction = r.direction.unit_vector();
-        let a = (unit_direction.y() + 1.) * 0.5;
-        start_color * (1. - a) + end_color * a
+/// Light reaching the hit straight from a randomly picked light, through a
+/// shadow ray
+fn direct_light(
+    r: &Ray,
+    rec: &HitRecord,
+    attenuation: Color,
+    pdf: &dyn Pdf,
+    world: &Arc<&dyn Hittable>,
+    lights: &LightSampler,
+) -> Color {
+    let Some(sample) = lights.sample(&rec.point, r.time) else {
+        return Color::new(0, 0, 0);
+    };
+
+    let to_light = Ray::new(rec.point, sample.direction, r.time).with_path_of(r);
+
+    // zero when the light is behind the surface
+    let scatter_pdf = (*rec.material).scattering_pdf(r, rec, &to_light);
+    if scatter_pdf <= 0. {
+        return Color::new(0, 0, 0);
     }
+
+    let radiance = lights.radiance(&sample, &to_light, **world);
+
+    // attenuation * scatter_pdf is the share of the light scattered towards r,
+    // pdf is how likely scattering would have found the light instead
+    let weight = power_heuristic(sample.pdf, pdf.value(&sample.direction));
+
+    radiance * attenuation * (scatter_pdf * weight / sample.pdf)
+}
@@ -0,0 +1,342 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{
+    degrees_to_radians,
+    output::ImageError,
+    random_f64,
+    Color,
+    Vec3,
+    PI,
+};
+
+/// Equirectangular image of the surroundings used as the background. The top
+/// row of the image is straight up and the center looks down -z
+pub struct EnvironmentMap {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+    /// Turns the map around the vertical axis, in degrees
+    pub rotation: f64,
+    /// Scales every pixel of the map
+    pub intensity: f64,
+    /// Cumulative brightness of each row, normalised so the last is 1
+    row_cdf: Vec<f64>,
+    /// Cumulative brightness of each pixel within its row
+    column_cdfs: Vec<Vec<f64>>,
+    /// Luminance weighted by solid angle of every pixel
+    weights: Vec<f64>,
+    weight_total: f64,
+}
+
+impl EnvironmentMap {
+    /// Reads a Radiance .hdr or OpenEXR file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<EnvironmentMap, ImageError> {
+        let path = path.as_ref();
+
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        let (width, height, pixels) = match extension.as_deref() {
+            Some("hdr") => read_hdr(&fs::read(path)?)?,
+            Some("exr") => read_exr(path)?,
+            _ => return Err(ImageError::UnsupportedFormat(path.to_path_buf())),
+        };
+
+        Ok(EnvironmentMap::from_pixels(width, height, pixels))
+    }
+
+    /// Pixels are linear colors in row major order starting from the top left
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> EnvironmentMap {
+        assert_eq!(pixels.len(), width * height, "pixel count doesn't match the size");
+
+        // pixels near the poles cover less of the sphere, so are less likely
+        // to be seen and should be sampled less
+        let weights: Vec<f64> = pixels
+            .iter()
+            .enumerate()
+            .map(|(i, color)| {
+                let theta = PI * ((i / width) as f64 + 0.5) / height as f64;
+                luminance(color) * theta.sin()
+            })
+            .collect();
+
+        let mut column_cdfs = Vec::with_capacity(height);
+        let mut row_sums = Vec::with_capacity(height);
+
+        for row in weights.chunks(width) {
+            let (cdf, sum) = normalised_cdf(row);
+            column_cdfs.push(cdf);
+            row_sums.push(sum);
+        }
+
+        let (row_cdf, weight_total) = normalised_cdf(&row_sums);
+
+        EnvironmentMap {
+            width,
+            height,
+            pixels,
+            rotation: 0.,
+            intensity: 1.,
+            row_cdf,
+            column_cdfs,
+            weights,
+            weight_total,
+        }
+    }
+
+    /// Radiance arriving from the direction
+    pub fn color(&self, direction: &Vec3) -> Color {
+        let (x, y, _) = self.pixel_of(direction);
+
+        self.pixels[y * self.width + x] * self.intensity
+    }
+
+    /// Picks a direction with probability proportional to how bright the map
+    /// is there, returns it with its pdf over solid angle. None if the map is
+    /// completely black
+    pub fn sample(&self) -> Option<(Vec3, f64)> {
+        if self.weight_total <= 0. {
+            return None;
+        }
+
+        let y = sample_cdf(&self.row_cdf, random_f64());
+        let x = sample_cdf(&self.column_cdfs[y], random_f64());
+
+        // uniform within the chosen pixel
+        let u = (x as f64 + random_f64()) / self.width as f64;
+        let v = (y as f64 + random_f64()) / self.height as f64;
+
+        let theta = v * PI;
+        let phi = (u - 0.5) * 2. * PI - degrees_to_radians(self.rotation);
+
+        let direction = Vec3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        );
+
+        let pdf = self.pdf(&direction);
+        if pdf <= 0. {
+            return None;
+        }
+
+        Some((direction, pdf))
+    }
+
+    /// Probability density over solid angle of sample returning the direction
+    pub fn pdf(&self, direction: &Vec3) -> f64 {
+        if self.weight_total <= 0. {
+            return 0.;
+        }
+
+        let (x, y, theta) = self.pixel_of(direction);
+        let sin_theta = theta.sin();
+
+        if sin_theta <= 0. {
+            return 0.;
+        }
+
+        // density over the unit square of image coordinates, then changed
+        // to solid angle
+        let image_pdf = self.weights[y * self.width + x] * (self.width * self.height) as f64
+            / self.weight_total;
+
+        image_pdf / (2. * PI * PI * sin_theta)
+    }
+
+    /// Pixel the direction lands on, along with its angle from straight up
+    fn pixel_of(&self, direction: &Vec3) -> (usize, usize, f64) {
+        let unit_direction = direction.unit_vector();
+
+        let theta = unit_direction.y().clamp(-1., 1.).acos();
+        let phi = unit_direction.x().atan2(-unit_direction.z()) + degrees_to_radians(self.rotation);
+
+        let u = (phi / (2. * PI) + 0.5).rem_euclid(1.);
+        let v = theta / PI;
+
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        (x, y, theta)
+    }
+}
+
+fn luminance(color: &Color) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+/// Running sum of the weights divided by their total, along with the total.
+/// An all zero row gets an even cdf so it can still be sampled from
+fn normalised_cdf(weights: &[f64]) -> (Vec<f64>, f64) {
+    let mut running = 0.;
+    let mut cdf: Vec<f64> = weights
+        .iter()
+        .map(|weight| {
+            running += weight;
+            running
+        })
+        .collect();
+
+    let total = running;
+
+    if total > 0. {
+        cdf.iter_mut().for_each(|c| *c /= total);
+    } else {
+        let n = cdf.len() as f64;
+        cdf.iter_mut().enumerate().for_each(|(i, c)| *c = (i + 1) as f64 / n);
+    }
+
+    (cdf, total)
+}
+
+/// Index of the first cdf entry above xi
+fn sample_cdf(cdf: &[f64], xi: f64) -> usize {
+    cdf.partition_point(|&c| c <= xi).min(cdf.len() - 1)
+}
+
+/// Decodes a Radiance rgbe file in the standard -Y +X orientation, scanlines
+/// can be flat or run length encoded
+fn read_hdr(data: &[u8]) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let decoding_error = |message: &str| ImageError::Decoding(format!("radiance hdr: {}", message));
+
+    let mut pos = 0;
+    let read_line = |pos: &mut usize| -> Result<String, ImageError> {
+        let end = data[*pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or_else(|| decoding_error("unexpected end of header"))?;
+
+        let line = String::from_utf8_lossy(&data[*pos..*pos + end]).into_owned();
+        *pos += end + 1;
+
+        Ok(line)
+    };
+
+    let magic = read_line(&mut pos)?;
+    if !magic.starts_with("#?") {
+        return Err(decoding_error("missing #? signature"));
+    }
+
+    // header ends at the first empty line
+    loop {
+        let line = read_line(&mut pos)?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(decoding_error(&format!("unsupported pixel format {}", format)));
+            }
+        }
+    }
+
+    let resolution = read_line(&mut pos)?;
+    let (height, width) = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (
+            height.parse::<usize>().map_err(|_| decoding_error("invalid height"))?,
+            width.parse::<usize>().map_err(|_| decoding_error("invalid width"))?,
+        ),
+        _ => return Err(decoding_error(&format!("unsupported orientation '{}'", resolution))),
+    };
+
+    if width == 0 || height == 0 {
+        return Err(decoding_error("image has no pixels"));
+    }
+
+    let next_byte = |pos: &mut usize| -> Result<u8, ImageError> {
+        let byte = *data.get(*pos).ok_or_else(|| decoding_error("unexpected end of pixel data"))?;
+        *pos += 1;
+        Ok(byte)
+    };
+
+    let mut pixels = Vec::with_capacity(width * height);
+    let mut scanline = vec![[0u8; 4]; width];
+
+    for _ in 0..height {
+        let is_rle = (8..=0x7fff).contains(&width)
+            && data.get(pos..pos + 4).is_some_and(|start| {
+                start[0] == 2 && start[1] == 2 && ((start[2] as usize) << 8 | start[3] as usize) == width
+            });
+
+        if is_rle {
+            pos += 4;
+
+            // components are stored one after another, each as a mix of
+            // literal and repeated byte runs
+            for component in 0..4 {
+                let mut x = 0;
+                while x < width {
+                    let count = next_byte(&mut pos)? as usize;
+
+                    let (run, repeated) = if count > 128 {
+                        (count - 128, true)
+                    } else {
+                        (count, false)
+                    };
+
+                    if run == 0 || x + run > width {
+                        return Err(decoding_error("bad run length"));
+                    }
+
+                    if repeated {
+                        let value = next_byte(&mut pos)?;
+                        scanline[x..x + run].iter_mut().for_each(|pixel| pixel[component] = value);
+                    } else {
+                        for pixel in &mut scanline[x..x + run] {
+                            pixel[component] = next_byte(&mut pos)?;
+                        }
+                    }
+
+                    x += run;
+                }
+            }
+        } else {
+            for pixel in scanline.iter_mut() {
+                for component in pixel.iter_mut() {
+                    *component = next_byte(&mut pos)?;
+                }
+            }
+        }
+
+        pixels.extend(scanline.iter().map(from_rgbe));
+    }
+
+    Ok((width, height, pixels))
+}
+
+fn from_rgbe(rgbe: &[u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::new(0, 0, 0);
+    }
+
+    // the middle of the range each 8 bit mantissa covers
+    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+fn read_exr(path: &Path) -> Result<(usize, usize, Vec<Color>), ImageError> {
+    let image = exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| (resolution.width(), vec![Color::default(); resolution.width() * resolution.height()]),
+        |(width, pixels), position, (r, g, b, _a): (f32, f32, f32, f32)| {
+            pixels[position.y() * *width + position.x()] = Color::new(r, g, b);
+        },
+    )
+    .map_err(|e| match e {
+        exr::error::Error::Io(e) => ImageError::Io(e),
+        e => ImageError::Decoding(e.to_string()),
+    })?;
+
+    let size = image.layer_data.size;
+    let (_, pixels) = image.layer_data.channel_data.pixels;
+
+    Ok((size.width(), size.height(), pixels))
+}
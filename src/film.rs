@@ -0,0 +1,103 @@
+use std::path::Path;
+
+use crate::{
+    output::{writer_for_path, ImageError, OutputOptions},
+    Color,
+};
+
+/// Accumulated samples of a single pixel
+#[derive(Clone, Copy, Default)]
+pub struct FilmPixel {
+    /// Sum of every sample's color multiplied by its weight
+    pub color_sum: Color,
+    pub weight_sum: f64,
+    pub sample_count: u32,
+}
+
+impl FilmPixel {
+    pub fn add_sample(&mut self, color: Color, weight: f64) {
+        self.color_sum += color * weight;
+        self.weight_sum += weight;
+        self.sample_count += 1;
+    }
+
+    /// Weighted average of the samples, black if there are none
+    pub fn color(&self) -> Color {
+        if self.weight_sum > 0. {
+            self.color_sum / self.weight_sum
+        } else {
+            Color::default()
+        }
+    }
+}
+
+/// Framebuffer of linear rgb pixels, stored in row major order starting from
+/// the top left
+pub struct Film {
+    width: u32,
+    height: u32,
+    pixels: Vec<FilmPixel>,
+}
+
+impl Film {
+    pub fn new(width: u32, height: u32) -> Film {
+        Film {
+            width,
+            height,
+            pixels: vec![FilmPixel::default(); (width * height) as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> &FilmPixel {
+        &self.pixels[self.index(x, y)]
+    }
+
+    pub fn pixel_mut(&mut self, x: u32, y: u32) -> &mut FilmPixel {
+        let idx = self.index(x, y);
+        &mut self.pixels[idx]
+    }
+
+    pub fn pixels(&self) -> &[FilmPixel] {
+        &self.pixels
+    }
+
+    pub fn add_sample(&mut self, x: u32, y: u32, color: Color, weight: f64) {
+        self.pixel_mut(x, y).add_sample(color, weight);
+    }
+
+    pub fn color(&self, x: u32, y: u32) -> Color {
+        self.pixel(x, y).color()
+    }
+
+    /// Final color of every pixel in row major order
+    pub fn colors(&self) -> Vec<Color> {
+        self.pixels.iter().map(|pixel| pixel.color()).collect()
+    }
+
+    /// Writes the film in the format matching the path's extension
+    pub fn save<P: AsRef<Path>>(&self, path: P, options: OutputOptions) -> Result<(), ImageError> {
+        let path = path.as_ref();
+
+        writer_for_path(path, options)?.write(self, path)
+    }
+
+    /// Replaces a whole row at once, row must be width pixels long
+    pub fn set_row(&mut self, y: u32, row: &[FilmPixel]) {
+        let start = self.index(0, y);
+        self.pixels[start..start + self.width as usize].copy_from_slice(row);
+    }
+
+    fn index(&self, x: u32, y: u32) -> usize {
+        assert!(x < self.width && y < self.height, "pixel ({x}, {y}) is outside the film");
+
+        (y * self.width + x) as usize
+    }
+}
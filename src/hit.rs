@@ -1,6 +1,9 @@
 use std::vec::Vec;
 use std::rc::Rc;
 use crate::{
+    aabb::Aabb,
+    random_f64,
+    Color,
     Ray,
     Vec3,
     material::Material,
@@ -10,6 +13,50 @@ type Point3 = Vec3;
 
 pub trait Hittable: Send + Sync {
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord>;
+
+    /// Box enclosing the whole object, used to build acceleration structures
+    fn bounding_box(&self) -> Aabb;
+
+    /// Probability density over solid angle of random picking direction
+    /// from origin at time, 0 for objects that can't be sampled
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.
+    }
+
+    /// Random direction from origin towards the object as it is at time
+    fn random(&self, _origin: &Point3, _time: f64) -> Vec3 {
+        Vec3::new(1, 0, 0)
+    }
+
+    /// If the object gives off light, lights are sampled directly when
+    /// rendering
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// Share of each channel of light that gets along r from ray_tmin to
+    /// ray_tmax through the object, for shadow rays. Solid objects block all
+    /// of it where they're hit, volumes let part of it through
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        if self.hit(r, ray_tmin, ray_tmax).is_some() {
+            Color::new(0, 0, 0)
+        } else {
+            Color::new(1, 1, 1)
+        }
+    }
+
+    /// Weight of each channel of light along r from ray_tmin to ray_tmax that
+    /// hit leaves out. Volumes whose channels thin out differently track a
+    /// single channel in hit, this weighs the others against it where the
+    /// ray gets through
+    fn transmittance_correction(&self, _r: &Ray, _ray_tmin: f64, _ray_tmax: f64) -> Color {
+        Color::new(1, 1, 1)
+    }
+
+    /// If transmittance_correction can be anything but 1
+    fn has_chromatic_volume(&self) -> bool {
+        false
+    }
 }
 
 pub struct HitRecord {
@@ -18,6 +65,12 @@ pub struct HitRecord {
     pub t: f64,
     pub front_face: bool,
     pub material: Rc<dyn Material>,
+    /// Surface coordinates of the hit, used to look up textures
+    pub u: f64,
+    pub v: f64,
+    /// Weights of a triangle's second and third vertices at the hit point,
+    /// the first vertex has weight 1 - u - v
+    pub barycentric: Option<(f64, f64)>,
 }
 
 pub struct HittableList {
@@ -39,6 +92,13 @@ impl HittableList {
     pub fn add(&mut self, obj: Rc<dyn Hittable>) {
         self.objects.push(obj);
     }
+
+    /// Every object in the list that emits light
+    pub fn lights(&self) -> HittableList {
+        HittableList {
+            objects: self.objects.iter().filter(|obj| obj.is_emissive()).cloned().collect(),
+        }
+    }
 }
 
 impl Hittable for HittableList {
@@ -58,6 +118,66 @@ impl Hittable for HittableList {
 
         hit_record
     }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects
+            .iter()
+            .fold(Aabb::empty(), |bbox, object| Aabb::surrounding(&bbox, &object.bounding_box()))
+    }
+
+    /// Average of the objects' densities, matching random picking an object
+    /// uniformly
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.;
+        }
+
+        let sum: f64 = self.objects.iter().map(|object| object.pdf_value(origin, direction, time)).sum();
+
+        sum / self.objects.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        if self.objects.is_empty() {
+            return Vec3::new(1, 0, 0);
+        }
+
+        let idx = ((random_f64() * self.objects.len() as f64) as usize).min(self.objects.len() - 1);
+
+        self.objects[idx].random(origin, time)
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.objects.iter().any(|object| object.is_emissive())
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let mut res = Color::new(1, 1, 1);
+
+        for object in &self.objects {
+            res *= object.transmittance(r, ray_tmin, ray_tmax);
+
+            if res.length_squared() <= 0. {
+                break;
+            }
+        }
+
+        res
+    }
+
+    fn transmittance_correction(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let mut res = Color::new(1, 1, 1);
+
+        for object in self.objects.iter().filter(|object| object.has_chromatic_volume()) {
+            res *= object.transmittance_correction(r, ray_tmin, ray_tmax);
+        }
+
+        res
+    }
+
+    fn has_chromatic_volume(&self) -> bool {
+        self.objects.iter().any(|object| object.has_chromatic_volume())
+    }
 }
 
 impl Default for HittableList {
@@ -77,6 +197,9 @@ impl HitRecord {
             // default value to allow function creation
             front_face: false,
             material,
+            u: 0.,
+            v: 0.,
+            barycentric: None,
         };
 
         res.set_face_normal(r, &normal);
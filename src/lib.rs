@@ -4,9 +4,30 @@ pub mod scene;
 pub mod hit;
 pub mod camera;
 pub mod material;
+pub mod medium;
+pub mod motion;
 pub mod pool;
+pub mod aabb;
+pub mod bvh;
+pub mod obj;
+pub mod output;
+pub mod film;
+pub mod background;
+pub mod environment;
+pub mod light;
+pub mod onb;
+pub mod pdf;
+pub mod perlin;
+pub mod spectrum;
+pub mod texture;
+pub mod transform;
+pub mod volume;
 pub mod shapes {
     pub mod sphere;
+    pub mod triangle;
+    pub mod mesh;
+    pub mod quad;
+    pub mod cuboid;
 }
 
 use std::rc::Rc;
@@ -118,7 +139,7 @@ pub fn radians_to_degrees(radians: f64) -> f64 {
 }
 
 /// lerp (linear blend) between two colors
-pub fn create_lerp_func(start_color: Color, end_color: Color) -> Box<dyn Fn(&Ray) -> Color> {
+pub fn create_lerp_func(start_color: Color, end_color: Color) -> Box<dyn Fn(&Ray) -> Color + Send + Sync> {
     // blendedValue = (1 - a) * startValue + a * endValue
     // Start and end value are colors
     // a is 0 - 1
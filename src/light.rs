@@ -0,0 +1,174 @@
+use crate::{
+    background::Background,
+    environment::EnvironmentMap,
+    hit::{Hittable, HittableList},
+    pdf::{HittablePdf, Pdf},
+    random_f64,
+    spectrum,
+    Color,
+    Ray,
+    Vec3,
+    INFINITY,
+};
+
+type Point3 = Vec3;
+
+/// Lights that can be sampled directly, the emissive objects of the world
+/// and an environment map background. Each is picked with equal chance
+pub struct LightSampler<'a> {
+    objects: &'a HittableList,
+    environment: Option<&'a EnvironmentMap>,
+}
+
+/// Direction towards a light picked by LightSampler::sample
+pub struct LightSample {
+    pub direction: Vec3,
+    /// Density over solid angle of picking both the light and the direction
+    pub pdf: f64,
+    /// Index into the light objects, None for the environment
+    pub light: Option<usize>,
+}
+
+impl<'a> LightSampler<'a> {
+    /// objects should come from HittableList::lights, other backgrounds than
+    /// an environment map are only found by scattering
+    pub fn new(objects: &'a HittableList, background: &'a Background) -> LightSampler<'a> {
+        let environment = match background {
+            Background::Environment(environment) => Some(environment),
+            _ => None,
+        };
+
+        LightSampler {
+            objects,
+            environment,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.objects.len() + self.environment.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Picks a light and a direction from origin towards it as it is at time
+    pub fn sample(&self, origin: &Point3, time: f64) -> Option<LightSample> {
+        if self.is_empty() {
+            return None;
+        }
+
+        let count = self.len() as f64;
+        let idx = ((random_f64() * count) as usize).min(self.len() - 1);
+
+        let sample = match self.objects.objects.get(idx) {
+            Some(object) => {
+                let pdf = HittablePdf::new(object.as_ref(), *origin, time);
+                let direction = pdf.generate();
+
+                LightSample {
+                    direction,
+                    pdf: pdf.value(&direction) / count,
+                    light: Some(idx),
+                }
+            }
+            None => {
+                let (direction, pdf) = self.environment?.sample()?;
+
+                LightSample {
+                    direction,
+                    pdf: pdf / count,
+                    light: None,
+                }
+            }
+        };
+
+        if sample.pdf <= 0. {
+            return None;
+        }
+
+        Some(sample)
+    }
+
+    /// Light arriving along r, the shadow ray towards the sample, dimmed by
+    /// volumes and black if something solid is in the way. Spectral when r
+    /// carries wavelengths
+    pub fn radiance(&self, sample: &LightSample, r: &Ray, world: &dyn Hittable) -> Color {
+        match sample.light {
+            Some(idx) => {
+                let Some(rec) = self.objects.objects[idx].hit(r, 0.001, INFINITY) else {
+                    return Color::new(0, 0, 0);
+                };
+
+                // stop just short of the light so it doesn't shadow itself
+                let transmittance = world.transmittance(r, 0.001, rec.t - 1e-6 * rec.t.abs().max(1.));
+                if transmittance.length_squared() <= 0. {
+                    return transmittance;
+                }
+
+                let emitted = match r.color.wavelengths() {
+                    Some(wavelengths) => (*rec.material).emitted_spectrum(r, &rec, wavelengths),
+                    None => (*rec.material).emitted(r, &rec),
+                };
+
+                emitted * transmittance * r.interior_transmittance(rec.t)
+            }
+            None => match self.environment {
+                Some(environment) => {
+                    let color = match r.color.wavelengths() {
+                        Some(wavelengths) => spectrum::rgb_to_spectrum(&environment.color(&sample.direction), wavelengths),
+                        None => environment.color(&sample.direction),
+                    };
+
+                    color * world.transmittance(r, 0.001, INFINITY) * r.interior_transmittance(INFINITY)
+                }
+                None => Color::new(0, 0, 0),
+            },
+        }
+    }
+
+    /// Density of sample picking the direction of r, where r first hits the
+    /// world at t
+    pub fn hit_pdf(&self, r: &Ray, t: f64) -> f64 {
+        if self.is_empty() {
+            return 0.;
+        }
+
+        // only the light actually hit could have been sampled towards, any
+        // behind it would have been blocked
+        let pdf: f64 = self
+            .objects
+            .objects
+            .iter()
+            .filter(|object| object.hit(r, 0.001, INFINITY).is_some_and(|rec| same_hit(rec.t, t)))
+            .map(|object| object.pdf_value(&r.origin, &r.direction, r.time))
+            .sum();
+
+        pdf / self.len() as f64
+    }
+
+    /// Density of sample picking the direction of a ray that missed the world
+    pub fn environment_pdf(&self, direction: &Vec3) -> f64 {
+        match self.environment {
+            Some(environment) => environment.pdf(direction) / self.len() as f64,
+            None => 0.,
+        }
+    }
+}
+
+/// Multiple importance sampling weight of a sample taken with pdf, when
+/// other_pdf could also have produced it
+pub fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let a = pdf * pdf;
+    let b = other_pdf * other_pdf;
+
+    if a + b <= 0. {
+        return 0.;
+    }
+
+    a / (a + b)
+}
+
+fn same_hit(a: f64, b: f64) -> bool {
+    (a - b).abs() <= 1e-6 * a.abs().max(1.)
+}
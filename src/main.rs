@@ -1,10 +1,11 @@
 use std::{
     env,
+    process,
     time::Instant,
 };
 
 use ray_tracer::{
-    camera::Camera, generate_world, vec3::Vec3,
+    bvh::{FlatBvh, SplitMethod}, camera::Camera, generate_world, scene::load_scene, vec3::Vec3,
 };
 
 type Point3 = Vec3;
@@ -16,33 +17,54 @@ fn main() {
     let args: Vec<String> = env::args().collect();
 
     let mut width: u32 = 400;
-    let mut image_path = String::from("./imgs/image.ppm");
+    let mut image_path = String::from("./imgs/image.png");
+    let mut scene_path: Option<String> = None;
 
     (1..args.len()).for_each(|i| {
         if let Ok(x) = args[i].parse::<u32>() {
             width = x;
+        } else if args[i].ends_with(".toml") {
+            scene_path = Some(args[i].clone());
         } else {
             image_path = args[i].clone();
         }
     });
 
-    let mut camera = Camera::default();
-    camera.image_width = 200;
-    camera.samples_per_pixel = 100;
-    camera.max_ray_bounce_depth = 50;
-    camera.aspect_ratio = 16. / 9.;
+    let (mut camera, world) = match scene_path {
+        Some(scene_path) => match load_scene(&scene_path) {
+            Ok(scene) => (scene.camera, scene.world),
+            Err(e) => {
+                eprintln!("Unable to load scene: {e}");
+                process::exit(1);
+            }
+        },
+        None => {
+            let mut camera = Camera::default();
+            camera.image_width = 200;
+            camera.samples_per_pixel = 100;
+            camera.max_ray_bounce_depth = 50;
+            camera.aspect_ratio = 16. / 9.;
 
-    camera.look_from = Point3::new(13, 2, 3);
-    camera.look_at = Point3::new(0, 0, 0);
+            camera.look_from = Point3::new(13, 2, 3);
+            camera.look_at = Point3::new(0, 0, 0);
 
-    camera.defocus_angle = 0.6;
-    camera.focus_dist = 10.;
+            camera.defocus_angle = 0.6;
+            camera.focus_dist = 10.;
 
-    let world = generate_world();
+            (camera, generate_world())
+        }
+    };
+
+    // lights are pulled out before the world is moved into the bvh
+    let lights = world.lights();
+    let world = FlatBvh::new(world, SplitMethod::Sah);
 
     let time_started = Instant::now();
 
-    camera.render(&world, &image_path);
+    if let Err(e) = camera.render(&world, &lights, &image_path) {
+        eprintln!("Unable to write image: {e}");
+        process::exit(1);
+    }
 
     println!("Render took {} secs", time_started.elapsed().as_secs());
 }
@@ -1,30 +1,93 @@
+use std::rc::Rc;
+
 use crate::{
     hit::HitRecord,
+    pdf::{CosinePdf, Pdf, SpherePdf},
+    spectrum::{self, Blackbody, PathColor, RefractiveIndex, Wavelengths},
+    texture::{SolidColor, Texture},
     Color,
     Ray,
     Vec3,
     random_f64,
+    PI,
 };
 
+/// How a material sends light onwards from a hit
+pub struct ScatterRecord {
+    pub attenuation: Color,
+    pub kind: ScatterKind,
+}
+
+pub enum ScatterKind {
+    /// A single outgoing ray, lights can't be sampled towards it
+    Specular(Ray),
+    /// Outgoing directions follow the pdf
+    Diffuse(Box<dyn Pdf>),
+}
+
 pub trait Material {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)>;
+    /// None when the light is absorbed
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord>;
+
+    /// Light given off by the surface at the hit, black unless the material
+    /// is a light source
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0, 0, 0)
+    }
+
+    /// Light given off at each of the wavelengths of a spectral path, by
+    /// default the emitted color as a smooth spectrum
+    fn emitted_spectrum(&self, r_in: &Ray, rec: &HitRecord, wavelengths: &Wavelengths) -> Color {
+        spectrum::rgb_to_spectrum(&self.emitted(r_in, rec), wavelengths)
+    }
+
+    /// If emitted can ever give off light
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    /// If scatter's attenuation is already per wavelength on spectral paths,
+    /// rather than a color to be turned into a spectrum
+    fn spectral_attenuation(&self) -> bool {
+        false
+    }
+
+    /// Share of the light arriving along scattered that is sent back along
+    /// r_in, divided by the attenuation. Only used for diffuse scattering
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        0.
+    }
 }
 
 pub struct Lambertian {
-    albedo: Color,
+    texture: Rc<dyn Texture>,
 }
 
 pub struct Metal {
-    albedo: Color,
+    texture: Rc<dyn Texture>,
     fuzz: f64,
 }
 
 pub struct Dielectric {
-    refraction_index: f64,
+    refraction_index: RefractiveIndex,
+    /// Share of each channel lost per unit of distance travelled inside
+    absorption: Color,
+}
+
+/// Emits the same light in every direction and reflects nothing
+pub struct DiffuseLight {
+    emit: Color,
+    /// Used on spectral paths instead of emit's color
+    spectrum: Option<Blackbody>,
+}
+
+/// Phase function of a volume, scatters evenly in every direction
+pub struct Isotropic {
+    texture: Rc<dyn Texture>,
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Ray, Color)> {
+    fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
         fn reflectance(cos: f64, refraction_index: f64) -> f64 {
             let r0: f64 =
                 ((1. - refraction_index) / (1. + refraction_index))
@@ -33,12 +96,13 @@ impl Material for Dielectric {
             r0 + (1. - r0) * (1. - cos).powi(5)
         }
 
-        let attenuation = Color::new(1, 1, 1);
+        // only rays carrying a wavelength can split into colors
+        let refraction_index = self.refraction_index.at(r_in.color.wavelength());
 
         let ri = if rec.front_face {
-            1. / self.refraction_index
+            1. / refraction_index
         } else {
-            self.refraction_index
+            refraction_index
         };
 
         let unit_dir = r_in.direction.unit_vector();
@@ -47,29 +111,74 @@ impl Material for Dielectric {
         let sin_theta = (1. - cos_theta * cos_theta).sqrt();
         let cannot_refract = ri * sin_theta > 1.; 
 
-        let direction = if cannot_refract || reflectance(cos_theta, ri) > random_f64() {
+        let refracts = !cannot_refract && reflectance(cos_theta, ri) <= random_f64();
+
+        let direction = if refracts {
+            Vec3::refract(unit_dir, rec.normal, ri)
+        } else {
             // must reflect
             Vec3::reflect(&unit_dir, &rec.normal)
-        } else {
-            Vec3::refract(unit_dir, rec.normal, ri)
         };
 
-        let scattered = Ray::new(rec.point, direction);
+        let mut scattered = Ray::new(rec.point, direction, r_in.time).with_path_of(r_in);
+
+        // refracting in puts the ray inside this glass, where the camera
+        // tints it by distance whatever it hits next. Refracting out leaves
+        // it in the clear
+        if refracts {
+            scattered.absorption = if rec.front_face {
+                self.absorption
+            } else {
+                Color::new(0, 0, 0)
+            };
+        }
 
-        Some((scattered, attenuation))
+        // the other wavelengths of a spectral path would have gone elsewhere
+        if let (PathColor::Spectral(wavelengths), false) = (&mut scattered.color, self.refraction_index.is_constant()) {
+            wavelengths.secondary = false;
+        }
+
+        Some(ScatterRecord {
+            attenuation: Color::new(1, 1, 1),
+            kind: ScatterKind::Specular(scattered),
+        })
     }
 }
 
 impl Dielectric {
-    pub fn new(refraction_index: f64) -> Dielectric {
+    /// Perfectly clear. An index changing with wavelength only disperses
+    /// light when the camera traces wavelengths
+    pub fn new(refraction_index: impl Into<RefractiveIndex>) -> Dielectric {
+        Dielectric {
+            refraction_index: refraction_index.into(),
+            absorption: Color::new(0, 0, 0),
+        }
+    }
+
+    /// Tinted so light that travelled distance inside is left with color,
+    /// thicker parts are darker and more saturated
+    pub fn with_absorption(refraction_index: impl Into<RefractiveIndex>, color: Color, distance: f64) -> Dielectric {
+        assert!(distance > 0., "absorption distance must be positive");
+
+        let mut absorption = Color::default();
+        for i in 0..3 {
+            // fully black channels would give infinite absorption
+            absorption[i] = -color[i].clamp(1e-6, 1.).ln() / distance;
+        }
+
         Dielectric {
-            refraction_index,
+            refraction_index: refraction_index.into(),
+            absorption,
         }
     }
 }
 
 impl Metal {
     pub fn new(albedo: Color, fuzz: f64) -> Metal {
+        Metal::from_texture(Rc::from(SolidColor::new(albedo)), fuzz)
+    }
+
+    pub fn from_texture(texture: Rc<dyn Texture>, fuzz: f64) -> Metal {
         let fuzz = if fuzz < 1. {
             fuzz
         } else {
@@ -77,16 +186,61 @@ impl Metal {
         };
 
         Metal {
-            albedo,
+            texture,
             fuzz,
         }
     }
 }
 
+impl DiffuseLight {
+    pub fn new(emit: Color) -> DiffuseLight {
+        DiffuseLight {
+            emit,
+            spectrum: None,
+        }
+    }
+
+    /// Glows like an object heated to temperature in kelvin, bright enough
+    /// for its color to have a luminance of intensity
+    pub fn blackbody(temperature: f64, intensity: f64) -> DiffuseLight {
+        let spectrum = Blackbody::new(temperature, intensity);
+
+        DiffuseLight {
+            emit: spectrum.to_rgb_clipped(),
+            spectrum: Some(spectrum),
+        }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        self.emit
+    }
+
+    fn emitted_spectrum(&self, _r_in: &Ray, _rec: &HitRecord, wavelengths: &Wavelengths) -> Color {
+        match &self.spectrum {
+            Some(spectrum) => spectrum.to_spectrum(wavelengths),
+            None => spectrum::rgb_to_spectrum(&self.emit, wavelengths),
+        }
+    }
+
+    fn is_emissive(&self) -> bool {
+        true
+    }
+}
+
 impl Lambertian {
     pub fn new(albedo: Color) -> Lambertian {
+        Lambertian::from_texture(Rc::from(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(texture: Rc<dyn Texture>) -> Lambertian {
         Lambertian {
-            albedo,
+            texture,
         }
     }
 }
@@ -96,22 +250,25 @@ impl Material for Metal {
         &self,
         r_in: &Ray,
         rec: &HitRecord
-        ) -> Option<(Ray, Color)> where Self: Sized
+        ) -> Option<ScatterRecord> where Self: Sized
     {
         let mut reflected = Vec3::reflect(&r_in.direction, &rec.normal);
 
         // fuzzing
         reflected = reflected.unit_vector() + (Vec3::random_unit_vec() * self.fuzz);
 
-        let scattered = Ray::new(rec.point, reflected);
-        let attenuation =  self.albedo;
+        let scattered = Ray::new(rec.point, reflected, r_in.time).with_path_of(r_in);
+        let attenuation = self.texture.value(rec.u, rec.v, &rec.point);
 
         // absorbed by surface if fuzziness moves ray inside sphere
         if Vec3::dot(&scattered.direction, &rec.normal) <= 0. {
             return None;
         }
 
-        Some((scattered, attenuation))
+        Some(ScatterRecord {
+            attenuation,
+            kind: ScatterKind::Specular(scattered),
+        })
     }
 }
 
@@ -120,14 +277,47 @@ impl Material for Lambertian {
         &self,
         _r_in: &Ray,
         rec: &HitRecord)
-        -> Option<(Ray, Color)> where Self: Sized
+        -> Option<ScatterRecord> where Self: Sized
     {
-        let mut scatter_direction = rec.normal + Vec3::random_unit_vec();
+        Some(ScatterRecord {
+            attenuation: self.texture.value(rec.u, rec.v, &rec.point),
+            kind: ScatterKind::Diffuse(Box::from(CosinePdf::new(&rec.normal))),
+        })
+    }
 
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal;
+    /// Cosine of the angle from the normal over pi
+    fn scattering_pdf(&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f64 {
+        let cos_theta = Vec3::dot(&rec.normal, &scattered.direction.unit_vector());
+
+        if cos_theta > 0. {
+            cos_theta / PI
+        } else {
+            0.
         }
+    }
+}
+
+impl Isotropic {
+    pub fn new(albedo: Color) -> Isotropic {
+        Isotropic::from_texture(Rc::from(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture(texture: Rc<dyn Texture>) -> Isotropic {
+        Isotropic {
+            texture,
+        }
+    }
+}
+
+impl Material for Isotropic {
+    fn scatter(&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.texture.value(rec.u, rec.v, &rec.point),
+            kind: ScatterKind::Diffuse(Box::from(SpherePdf)),
+        })
+    }
 
-        Some((Ray::new(rec.point, scatter_direction), self.albedo))
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1. / (4. * PI)
     }
 }
@@ -0,0 +1,105 @@
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    material::{Isotropic, Material},
+    random_f64,
+    texture::Texture,
+    Color,
+    Ray,
+    Vec3,
+    INFINITY,
+};
+
+/// Volume of evenly spread particles filling a boundary, like fog or smoke.
+/// Rays are scattered at a random depth inside, more likely the denser it
+/// is. The boundary must be convex
+pub struct ConstantMedium {
+    boundary: Rc<dyn Hittable>,
+    neg_inv_density: f64,
+    phase_function: Rc<dyn Material>,
+}
+
+impl ConstantMedium {
+    /// Isotropic scattering tinted by the texture
+    pub fn new(boundary: Rc<dyn Hittable>, density: f64, texture: Rc<dyn Texture>) -> ConstantMedium {
+        ConstantMedium::with_phase_function(boundary, density, Rc::from(Isotropic::from_texture(texture)))
+    }
+
+    pub fn from_color(boundary: Rc<dyn Hittable>, density: f64, albedo: Color) -> ConstantMedium {
+        ConstantMedium::with_phase_function(boundary, density, Rc::from(Isotropic::new(albedo)))
+    }
+
+    /// Scatters with phase_function instead of evenly in every direction
+    pub fn with_phase_function(
+        boundary: Rc<dyn Hittable>,
+        density: f64,
+        phase_function: Rc<dyn Material>,
+    ) -> ConstantMedium {
+        assert!(density > 0., "medium density must be positive");
+
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+}
+
+/// Where r is inside boundary between ray_tmin and ray_tmax, as the t it
+/// enters and the t it leaves. The boundary must be convex
+pub(crate) fn boundary_segment(boundary: &dyn Hittable, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<(f64, f64)> {
+    // where the whole line enters and leaves the boundary, so rays starting
+    // inside still find both
+    let enter = boundary.hit(r, -INFINITY, INFINITY)?;
+    let exit = boundary.hit(r, enter.t + 0.0001, INFINITY)?;
+
+    let t_enter = enter.t.max(ray_tmin).max(0.);
+    let t_exit = exit.t.min(ray_tmax);
+
+    if t_enter >= t_exit {
+        return None;
+    }
+
+    Some((t_enter, t_exit))
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let (t_enter, t_exit) = boundary_segment(self.boundary.as_ref(), r, ray_tmin, ray_tmax)?;
+
+        let ray_length = r.direction.length();
+        let distance_inside_boundary = (t_exit - t_enter) * ray_length;
+        let hit_distance = self.neg_inv_density * random_f64().ln();
+
+        // passed straight through
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+
+        let t = t_enter + hit_distance / ray_length;
+
+        // a particle has no surface, the normal is arbitrary
+        let mut rec = HitRecord::new(r.at(t), Vec3::new(1, 0, 0), t, r, self.phase_function.clone());
+        rec.normal = Vec3::new(1, 0, 0);
+        rec.front_face = true;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let Some((t_enter, t_exit)) = boundary_segment(self.boundary.as_ref(), r, ray_tmin, ray_tmax) else {
+            return Color::new(1, 1, 1);
+        };
+
+        let distance = (t_exit - t_enter) * r.direction.length();
+        let share = (distance / self.neg_inv_density).exp();
+
+        Color::new(share, share, share)
+    }
+}
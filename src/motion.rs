@@ -0,0 +1,64 @@
+use crate::Vec3;
+
+type Point3 = Vec3;
+
+/// Position that changes over time by moving in a straight line between
+/// keyframes. Before the first and after the last keyframe it stays still
+#[derive(Clone)]
+pub struct Motion {
+    /// (time, position) sorted by time, never empty
+    keys: Vec<(f64, Point3)>,
+}
+
+impl Motion {
+    /// Never moves
+    pub fn fixed(position: Point3) -> Motion {
+        Motion {
+            keys: vec![(0., position)],
+        }
+    }
+
+    /// Moves from start at time 0 to end at time 1
+    pub fn linear(start: Point3, end: Point3) -> Motion {
+        Motion {
+            keys: vec![(0., start), (1., end)],
+        }
+    }
+
+    /// Keyframes can be given in any order, panics if there are none
+    pub fn keyframes(mut keys: Vec<(f64, Point3)>) -> Motion {
+        assert!(!keys.is_empty(), "motion needs at least one keyframe");
+
+        keys.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+        Motion {
+            keys,
+        }
+    }
+
+    pub fn at(&self, time: f64) -> Point3 {
+        let next = self.keys.partition_point(|&(key_time, _)| key_time <= time);
+
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let (t0, p0) = self.keys[next - 1];
+        let (t1, p1) = self.keys[next];
+        let blend = (time - t0) / (t1 - t0);
+
+        p0 + (p1 - p0) * blend
+    }
+
+    pub fn is_fixed(&self) -> bool {
+        self.keys.len() == 1
+    }
+
+    /// Every keyframe's position, the path never leaves the box around them
+    pub fn positions(&self) -> impl Iterator<Item = Point3> + '_ {
+        self.keys.iter().map(|&(_, position)| position)
+    }
+}
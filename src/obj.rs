@@ -0,0 +1,610 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::{
+    material::{Dielectric, Lambertian, Material, Metal},
+    shapes::mesh::{Mesh, MeshFace, MeshGroup},
+    Color,
+    Vec3,
+};
+
+type Point3 = Vec3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            ObjError::Parse { path, line, message } => {
+                write!(f, "{}:{}: {}", path.display(), line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { source, .. } => Some(source),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Loads a wavefront .obj file into a single mesh. Polygons are split into
+/// triangle fans and materials come from the file's mtllib entries, faces
+/// without a usemtl use default_material
+pub fn load_obj<P: AsRef<Path>>(path: P, default_material: Rc<dyn Material>) -> Result<Mesh, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+
+    let mut positions: Vec<Point3> = Vec::new();
+    let mut normals: Vec<Vec3> = Vec::new();
+    let mut texcoords: Vec<(f64, f64)> = Vec::new();
+    let mut faces: Vec<MeshFace> = Vec::new();
+    let mut groups: Vec<MeshGroup> = Vec::new();
+
+    // index 0 is always the default material
+    let mut materials: Vec<Rc<dyn Material>> = vec![default_material];
+    let mut library: HashMap<String, Rc<dyn Material>> = HashMap::new();
+    let mut material_indices: HashMap<String, usize> = HashMap::new();
+    let mut current_material = 0;
+
+    let mut group_name = String::from("default");
+    let mut group_start = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let parse_error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_number,
+            message,
+        };
+
+        let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        match keyword {
+            "v" => positions.push(parse_vec3(&args).map_err(parse_error)?),
+            "vn" => normals.push(parse_vec3(&args).map_err(parse_error)?),
+            "vt" => {
+                let u = parse_f64(args.first().copied()).map_err(parse_error)?;
+                let v = match args.get(1) {
+                    Some(v) => parse_f64(Some(v)).map_err(parse_error)?,
+                    None => 0.,
+                };
+                texcoords.push((u, v));
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(parse_error(format!("face needs at least 3 vertices, got {}", args.len())));
+                }
+
+                let corners = args
+                    .iter()
+                    .map(|arg| parse_face_vertex(arg, positions.len(), texcoords.len(), normals.len()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(parse_error)?;
+
+                // fan triangulation around the first corner, only correct for
+                // convex polygons
+                for k in 1..corners.len() - 1 {
+                    let tri = [corners[0], corners[k], corners[k + 1]];
+
+                    let normals = if tri.iter().all(|c| c.normal.is_some()) {
+                        Some(tri.map(|c| c.normal.unwrap()))
+                    } else {
+                        None
+                    };
+                    let texcoords = if tri.iter().all(|c| c.texcoord.is_some()) {
+                        Some(tri.map(|c| c.texcoord.unwrap()))
+                    } else {
+                        None
+                    };
+
+                    faces.push(MeshFace {
+                        positions: tri.map(|c| c.position),
+                        normals,
+                        texcoords,
+                        material: current_material,
+                    });
+                }
+            }
+            "g" | "o" => {
+                if faces.len() > group_start {
+                    groups.push(MeshGroup {
+                        name: group_name,
+                        faces: group_start..faces.len(),
+                    });
+                }
+
+                group_name = if args.is_empty() {
+                    String::from("default")
+                } else {
+                    args.join(" ")
+                };
+                group_start = faces.len();
+            }
+            "mtllib" => {
+                let dir = path.parent().unwrap_or(Path::new(""));
+
+                for file in &args {
+                    library.extend(load_mtl(dir.join(file))?);
+                }
+            }
+            "usemtl" => {
+                let name = args.join(" ");
+
+                current_material = match material_indices.get(&name) {
+                    Some(&index) => index,
+                    None => {
+                        let material = library
+                            .get(&name)
+                            .cloned()
+                            .ok_or_else(|| parse_error(format!("unknown material '{}'", name)))?;
+
+                        materials.push(material);
+                        material_indices.insert(name, materials.len() - 1);
+                        materials.len() - 1
+                    }
+                };
+            }
+            // smoothing groups, lines, points and free-form geometry
+            _ => log::debug!("{}:{}: ignoring '{}'", path.display(), line_number, keyword),
+        }
+    }
+
+    if faces.len() > group_start {
+        groups.push(MeshGroup {
+            name: group_name,
+            faces: group_start..faces.len(),
+        });
+    }
+
+    Ok(Mesh::new(positions, normals, texcoords, faces, groups, materials))
+}
+
+/// Reads the materials of an .mtl file, mapping each onto the closest of the
+/// renderer's materials. Transparent materials become Dielectric, ones with
+/// a reflection illumination model Metal and everything else Lambertian
+pub fn load_mtl<P: AsRef<Path>>(path: P) -> Result<HashMap<String, Rc<dyn Material>>, ObjError> {
+    let path = path.as_ref();
+    let source = read_file(path)?;
+
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlProperties)> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let parse_error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: i + 1,
+            message,
+        };
+
+        let mut tokens = line.split('#').next().unwrap_or("").split_whitespace();
+        let Some(keyword) = tokens.next() else {
+            continue;
+        };
+        let args: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if let Some((name, properties)) = current.take() {
+                materials.insert(name, properties.to_material());
+            }
+            current = Some((args.join(" "), MtlProperties::default()));
+            continue;
+        }
+
+        let Some((_, properties)) = current.as_mut() else {
+            return Err(parse_error(format!("'{}' before any newmtl", keyword)));
+        };
+
+        match keyword {
+            "Kd" => properties.diffuse = parse_vec3(&args).map_err(parse_error)?,
+            "Ks" => properties.specular = parse_vec3(&args).map_err(parse_error)?,
+            "Ns" => properties.shininess = parse_f64(args.first().copied()).map_err(parse_error)?,
+            "Ni" => properties.optical_density = Some(parse_f64(args.first().copied()).map_err(parse_error)?),
+            "d" => properties.dissolve = parse_f64(args.first().copied()).map_err(parse_error)?,
+            "Tf" => properties.transmission = Some(parse_vec3(&args).map_err(parse_error)?),
+            "Tr" => properties.dissolve = 1. - parse_f64(args.first().copied()).map_err(parse_error)?,
+            "illum" => {
+                properties.illum = args
+                    .first()
+                    .and_then(|arg| arg.parse().ok())
+                    .ok_or_else(|| parse_error(String::from("expected an illumination model number")))?;
+            }
+            // ambient, emissive and texture maps have no equivalent yet
+            _ => log::debug!("{}:{}: ignoring '{}'", path.display(), i + 1, keyword),
+        }
+    }
+
+    if let Some((name, properties)) = current.take() {
+        materials.insert(name, properties.to_material());
+    }
+
+    Ok(materials)
+}
+
+struct MtlProperties {
+    diffuse: Color,
+    specular: Color,
+    shininess: f64,
+    optical_density: Option<f64>,
+    /// Color of light passing through, taken as what's left after a unit of
+    /// distance
+    transmission: Option<Color>,
+    dissolve: f64,
+    illum: u32,
+}
+
+impl Default for MtlProperties {
+    fn default() -> Self {
+        // defaults from the mtl spec
+        MtlProperties {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::new(0, 0, 0),
+            shininess: 0.,
+            optical_density: None,
+            transmission: None,
+            dissolve: 1.,
+            illum: 2,
+        }
+    }
+}
+
+impl MtlProperties {
+    fn to_material(&self) -> Rc<dyn Material> {
+        let transparent = self.dissolve < 1. || matches!(self.illum, 4 | 6 | 7 | 9);
+        let reflective = matches!(self.illum, 3 | 5 | 8);
+
+        if transparent {
+            // a glass with no index given would be invisible, so assume glass
+            let refraction_index = self.optical_density.unwrap_or(1.5);
+
+            match self.transmission {
+                Some(color) => Rc::from(Dielectric::with_absorption(refraction_index, color, 1.)),
+                None => Rc::from(Dielectric::new(refraction_index)),
+            }
+        } else if reflective {
+            // blinn-phong exponent to roughness, higher exponents are sharper
+            let fuzz = (2. / (self.shininess + 2.)).sqrt();
+            Rc::from(Metal::new(self.specular, fuzz))
+        } else {
+            Rc::from(Lambertian::new(self.diffuse))
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct FaceVertex {
+    position: usize,
+    texcoord: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Parses v, v/vt, v//vn or v/vt/vn, indices are 1 based and negative
+/// ones count back from the latest element
+fn parse_face_vertex(
+    arg: &str,
+    position_count: usize,
+    texcoord_count: usize,
+    normal_count: usize,
+) -> Result<FaceVertex, String> {
+    let mut parts = arg.split('/');
+
+    let position = resolve_index(parts.next(), position_count, arg)?
+        .ok_or_else(|| format!("face vertex '{}' has no position", arg))?;
+    let texcoord = resolve_index(parts.next(), texcoord_count, arg)?;
+    let normal = resolve_index(parts.next(), normal_count, arg)?;
+
+    Ok(FaceVertex {
+        position,
+        texcoord,
+        normal,
+    })
+}
+
+fn resolve_index(part: Option<&str>, count: usize, arg: &str) -> Result<Option<usize>, String> {
+    let part = match part {
+        None | Some("") => return Ok(None),
+        Some(part) => part,
+    };
+
+    let index: i64 = part
+        .parse()
+        .map_err(|_| format!("invalid index '{}' in face vertex '{}'", part, arg))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("index {} in face vertex '{}' is out of range", index, arg));
+    }
+
+    Ok(Some(resolved as usize))
+}
+
+fn parse_f64(arg: Option<&str>) -> Result<f64, String> {
+    let arg = arg.ok_or_else(|| String::from("expected a number"))?;
+
+    arg.parse().map_err(|_| format!("invalid number '{}'", arg))
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+    if args.len() < 3 {
+        return Err(format!("expected 3 numbers, got {}", args.len()));
+    }
+
+    Ok(Vec3::new(
+        parse_f64(Some(args[0]))?,
+        parse_f64(Some(args[1]))?,
+        parse_f64(Some(args[2]))?,
+    ))
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|source| ObjError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+    use crate::{
+        hit::HitRecord,
+        material::{ScatterKind, ScatterRecord},
+        Ray,
+    };
+
+    /// Writes files into a directory of their own so mtllib paths resolve
+    fn write_files(test: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("obj-test-{}-{}", process::id(), test));
+        fs::create_dir_all(&dir).unwrap();
+
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+
+        dir
+    }
+
+    fn default_material() -> Rc<dyn Material> {
+        Rc::from(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn parse_obj(test: &str, source: &str) -> Result<Mesh, ObjError> {
+        let dir = write_files(test, &[("mesh.obj", source)]);
+        load_obj(dir.join("mesh.obj"), default_material())
+    }
+
+    fn parse_error_line(result: Result<Mesh, ObjError>) -> (usize, String) {
+        match result {
+            Err(ObjError::Parse { line, message, .. }) => (line, message),
+            Err(e) => panic!("expected a parse error, got '{}'", e),
+            Ok(_) => panic!("expected a parse error"),
+        }
+    }
+
+    /// Scatters a ray hitting the material head on
+    fn scatter_head_on(material: &Rc<dyn Material>) -> ScatterRecord {
+        let r = Ray::new(Point3::new(0, 0, 1), Vec3::new(0, 0, -1), 0.);
+        let rec = HitRecord::new(Point3::new(0, 0, 0), Vec3::new(0, 0, 1), 1., &r, material.clone());
+
+        material.scatter(&r, &rec).expect("material absorbed a head on ray")
+    }
+
+    const SQUARE: &str = "\
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+";
+
+    #[test]
+    fn face_vertex_forms() {
+        let source = format!("{}f 1 2 3\nf 1/1 2/2 3/3\nf 1//1 2//1 3//1\nf 1/1/1 2/2/1 3/3/1\n", SQUARE);
+        let mesh = parse_obj("forms", &source).unwrap();
+        let faces = mesh.faces();
+
+        assert_eq!(faces.len(), 4);
+        assert!(faces.iter().all(|f| f.positions == [0, 1, 2]));
+
+        assert_eq!(faces[0].texcoords, None);
+        assert_eq!(faces[0].normals, None);
+        assert_eq!(faces[1].texcoords, Some([0, 1, 2]));
+        assert_eq!(faces[1].normals, None);
+        assert_eq!(faces[2].texcoords, None);
+        assert_eq!(faces[2].normals, Some([0, 0, 0]));
+        assert_eq!(faces[3].texcoords, Some([0, 1, 2]));
+        assert_eq!(faces[3].normals, Some([0, 0, 0]));
+    }
+
+    #[test]
+    fn negative_indices_count_back() {
+        // each face only sees the elements before it
+        let source = format!("{}f -4/-4/-1 -3/-3/-1 -2/-2/-1\nv 2 2 2\nf -1 -2 -3\n", SQUARE);
+        let mesh = parse_obj("negative", &source).unwrap();
+        let faces = mesh.faces();
+
+        assert_eq!(faces[0].positions, [0, 1, 2]);
+        assert_eq!(faces[0].texcoords, Some([0, 1, 2]));
+        assert_eq!(faces[0].normals, Some([0, 0, 0]));
+        assert_eq!(faces[1].positions, [4, 3, 2]);
+    }
+
+    #[test]
+    fn polygons_split_into_fans_and_groups() {
+        let source = format!("{}# a comment\nf 1 2 3 4 # quad\ng top\nf 1 3 4\ng empty\n", SQUARE);
+        let mesh = parse_obj("fans", &source).unwrap();
+        let faces = mesh.faces();
+
+        assert_eq!(faces.len(), 3);
+        assert_eq!(faces[0].positions, [0, 1, 2]);
+        assert_eq!(faces[1].positions, [0, 2, 3]);
+
+        let groups = mesh.groups();
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].name, "default");
+        assert_eq!(groups[0].faces, 0..2);
+        assert_eq!(groups[1].name, "top");
+        assert_eq!(groups[1].faces, 2..3);
+
+        assert_eq!(mesh.positions().len(), 4);
+        assert_eq!(mesh.texcoords()[2], (1., 1.));
+        assert_eq!(mesh.normals()[0].as_string(), Vec3::new(0, 0, 1).as_string());
+    }
+
+    #[test]
+    fn usemtl_picks_library_materials() {
+        let mtl = "\
+newmtl red
+Kd 1 0 0
+newmtl glass
+Ni 1.5
+d 0.5
+";
+        let obj = format!("mtllib scene.mtl\n{}f 1 2 3\nusemtl red\nf 1 2 3\nusemtl glass\nf 1 2 3\nusemtl red\nf 1 2 3\n", SQUARE);
+        let dir = write_files("usemtl", &[("scene.obj", &obj), ("scene.mtl", mtl)]);
+        let mesh = load_obj(dir.join("scene.obj"), default_material()).unwrap();
+
+        let indices: Vec<usize> = mesh.faces().iter().map(|f| f.material).collect();
+        assert_eq!(indices, [0, 1, 2, 1]);
+        assert_eq!(mesh.materials().len(), 3);
+    }
+
+    #[test]
+    fn mtl_materials() {
+        let mtl = "\
+# materials
+newmtl matte
+Kd 0.2 0.4 0.6
+
+newmtl mirror
+Ks 0.9 0.8 0.7
+Ns 1000
+illum 3
+
+newmtl glass
+Ni 1.3
+Tf 0.5 1 1
+illum 7
+";
+        let dir = write_files("mtl", &[("scene.mtl", mtl)]);
+        let materials = load_mtl(dir.join("scene.mtl")).unwrap();
+
+        assert_eq!(materials.len(), 3);
+
+        let matte = scatter_head_on(&materials["matte"]);
+        assert!(matches!(matte.kind, ScatterKind::Diffuse(_)));
+        assert_eq!(matte.attenuation.as_string(), Color::new(0.2, 0.4, 0.6).as_string());
+
+        let mirror = scatter_head_on(&materials["mirror"]);
+        assert!(matches!(mirror.kind, ScatterKind::Specular(_)));
+        assert_eq!(mirror.attenuation.as_string(), Color::new(0.9, 0.8, 0.7).as_string());
+
+        // glass doesn't tint at the surface, only by distance inside
+        let glass = scatter_head_on(&materials["glass"]);
+        assert!(matches!(glass.kind, ScatterKind::Specular(_)));
+        assert_eq!(glass.attenuation.as_string(), Color::new(1, 1, 1).as_string());
+    }
+
+    #[test]
+    fn bad_faces_are_errors() {
+        let cases = [
+            ("f 1 2\n", "face needs at least 3 vertices, got 2"),
+            ("f 1 2 5\n", "index 5 in face vertex '5' is out of range"),
+            ("f 0 1 2\n", "index 0 in face vertex '0' is out of range"),
+            ("f -5 1 2\n", "index -5 in face vertex '-5' is out of range"),
+            ("f 1/5 2 3\n", "index 5 in face vertex '1/5' is out of range"),
+            ("f 1//2 2 3\n", "index 2 in face vertex '1//2' is out of range"),
+            ("f 1 a 3\n", "invalid index 'a' in face vertex 'a'"),
+            ("f /1 2 3\n", "face vertex '/1' has no position"),
+        ];
+
+        for (i, (face, expected)) in cases.iter().enumerate() {
+            let source = format!("{}{}", SQUARE, face);
+            let (line, message) = parse_error_line(parse_obj(&format!("bad-face-{}", i), &source));
+
+            assert_eq!(line, 10, "{}", face);
+            assert_eq!(message, *expected);
+        }
+    }
+
+    #[test]
+    fn bad_numbers_are_errors() {
+        let (line, message) = parse_error_line(parse_obj("bad-vertex", "v 0 0 0\nv 1 x 0\n"));
+        assert_eq!((line, message.as_str()), (2, "invalid number 'x'"));
+
+        let (line, message) = parse_error_line(parse_obj("short-normal", "vn 0 1\n"));
+        assert_eq!((line, message.as_str()), (1, "expected 3 numbers, got 2"));
+
+        let (line, message) = parse_error_line(parse_obj("empty-texcoord", "vt\n"));
+        assert_eq!((line, message.as_str()), (1, "expected a number"));
+    }
+
+    #[test]
+    fn unknown_material_is_an_error() {
+        let (line, message) = parse_error_line(parse_obj("unknown-material", "usemtl missing\n"));
+        assert_eq!((line, message.as_str()), (1, "unknown material 'missing'"));
+    }
+
+    #[test]
+    fn bad_mtl_is_an_error() {
+        let dir = write_files("bad-mtl", &[("before.mtl", "Kd 1 1 1\n"), ("illum.mtl", "newmtl a\nillum x\n")]);
+
+        match load_mtl(dir.join("before.mtl")) {
+            Err(ObjError::Parse { line: 1, message, .. }) => assert_eq!(message, "'Kd' before any newmtl"),
+            _ => panic!("expected a parse error on line 1"),
+        }
+        match load_mtl(dir.join("illum.mtl")) {
+            Err(ObjError::Parse { line: 2, message, .. }) => assert_eq!(message, "expected an illumination model number"),
+            _ => panic!("expected a parse error on line 2"),
+        }
+    }
+
+    #[test]
+    fn missing_files_are_io_errors() {
+        let dir = write_files("missing", &[("scene.obj", "mtllib nowhere.mtl\n")]);
+
+        match load_obj(dir.join("absent.obj"), default_material()) {
+            Err(ObjError::Io { path, .. }) => assert_eq!(path, dir.join("absent.obj")),
+            _ => panic!("expected an io error"),
+        }
+        // a missing library names the library, not the obj
+        match load_obj(dir.join("scene.obj"), default_material()) {
+            Err(ObjError::Io { path, .. }) => assert_eq!(path, dir.join("nowhere.mtl")),
+            _ => panic!("expected an io error"),
+        }
+    }
+}
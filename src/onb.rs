@@ -0,0 +1,35 @@
+use crate::Vec3;
+
+/// Orthonormal basis built around a direction, w points along it
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Onb {
+        let w = n.unit_vector();
+
+        // any vector not parallel to w works to start the cross products
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0, 1, 0)
+        } else {
+            Vec3::new(1, 0, 0)
+        };
+
+        let v = Vec3::cross(&w, &a).unit_vector();
+        let u = Vec3::cross(&w, &v);
+
+        Onb {
+            u,
+            v,
+            w,
+        }
+    }
+
+    /// Converts a vector given in this basis' coordinates to world space
+    pub fn transform(&self, v: &Vec3) -> Vec3 {
+        self.u * v[0] + self.v * v[1] + self.w * v[2]
+    }
+}
@@ -0,0 +1,273 @@
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use exr::prelude::f16;
+
+use crate::{
+    clamp,
+    film::Film,
+    Color,
+    Vec3,
+};
+
+/// Encodes a rendered film into an image file
+pub trait ImageWriter {
+    fn write(&self, film: &Film, path: &Path) -> Result<(), ImageError>;
+}
+
+#[derive(Debug)]
+pub enum ImageError {
+    Io(io::Error),
+    /// The encoder rejected the image
+    Encoding(String),
+    /// The file isn't a valid image of its format
+    Decoding(String),
+    /// The path's extension isn't a supported format
+    UnsupportedFormat(PathBuf),
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io(e) => write!(f, "{}", e),
+            ImageError::Encoding(message) => write!(f, "unable to encode image: {}", message),
+            ImageError::Decoding(message) => write!(f, "unable to decode image: {}", message),
+            ImageError::UnsupportedFormat(path) => {
+                write!(f, "unsupported image format for '{}'", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for ImageError {
+    fn from(e: io::Error) -> Self {
+        ImageError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for ImageError {
+    fn from(e: png::EncodingError) -> Self {
+        match e {
+            png::EncodingError::IoError(e) => ImageError::Io(e),
+            e => ImageError::Encoding(e.to_string()),
+        }
+    }
+}
+
+impl From<exr::error::Error> for ImageError {
+    fn from(e: exr::error::Error) -> Self {
+        match e {
+            exr::error::Error::Io(e) => ImageError::Io(e),
+            e => ImageError::Encoding(e.to_string()),
+        }
+    }
+}
+
+/// Integer bits per channel for png
+#[derive(Clone, Copy, Default)]
+pub enum BitDepth {
+    #[default]
+    Eight,
+    Sixteen,
+}
+
+/// Floating point size of each channel in exr files
+#[derive(Clone, Copy, Default)]
+pub enum ExrPrecision {
+    #[default]
+    Half,
+    Float,
+}
+
+/// Settings for formats that can be written more than one way
+#[derive(Clone, Copy, Default)]
+pub struct OutputOptions {
+    pub bit_depth: BitDepth,
+    pub exr_precision: ExrPrecision,
+}
+
+/// ASCII P3 ppm, always 8 bits per channel
+pub struct PpmWriter;
+
+pub struct PngWriter {
+    pub bit_depth: BitDepth,
+}
+
+/// OpenEXR, keeps the linear colors without clamping or gamma correction
+pub struct ExrWriter {
+    pub precision: ExrPrecision,
+}
+
+/// Radiance rgbe .hdr, keeps the linear colors without clamping or gamma
+/// correction
+pub struct HdrWriter;
+
+/// Picks the writer matching the path's extension
+pub fn writer_for_path(path: &Path, options: OutputOptions) -> Result<Box<dyn ImageWriter>, ImageError> {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(|extension| extension.to_ascii_lowercase());
+
+    match extension.as_deref() {
+        Some("ppm") => Ok(Box::from(PpmWriter)),
+        Some("png") => Ok(Box::from(PngWriter { bit_depth: options.bit_depth })),
+        Some("exr") => Ok(Box::from(ExrWriter { precision: options.exr_precision })),
+        Some("hdr") => Ok(Box::from(HdrWriter)),
+        _ => Err(ImageError::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+impl ImageWriter for PpmWriter {
+    fn write(&self, film: &Film, path: &Path) -> Result<(), ImageError> {
+        let mut res = String::new();
+
+        res.push_str(&format!("P3\n{} {}\n255\n", film.width(), film.height()));
+
+        for color in film.colors() {
+            res.push_str(&(color.get_color_256() + "\n"));
+        }
+
+        fs::write(path, res)?;
+
+        Ok(())
+    }
+}
+
+impl ImageWriter for PngWriter {
+    fn write(&self, film: &Film, path: &Path) -> Result<(), ImageError> {
+        let file = BufWriter::new(File::create(path)?);
+
+        let pixels = film.colors();
+
+        let mut encoder = png::Encoder::new(file, film.width(), film.height());
+        encoder.set_color(png::ColorType::Rgb);
+        // colors are stored with the same gamma 2 as ppm output
+        encoder.set_source_gamma(png::ScaledFloat::new(0.5));
+
+        let data: Vec<u8> = match self.bit_depth {
+            BitDepth::Eight => {
+                encoder.set_depth(png::BitDepth::Eight);
+
+                pixels
+                    .iter()
+                    .flat_map(|color| (0..3).map(|i| quantize(color[i], 256.) as u8))
+                    .collect()
+            }
+            BitDepth::Sixteen => {
+                encoder.set_depth(png::BitDepth::Sixteen);
+
+                // png stores 16 bit samples big endian
+                pixels
+                    .iter()
+                    .flat_map(|color| (0..3).flat_map(|i| (quantize(color[i], 65536.) as u16).to_be_bytes()))
+                    .collect()
+            }
+        };
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&data)?;
+        writer.finish()?;
+
+        Ok(())
+    }
+}
+
+impl ImageWriter for ExrWriter {
+    fn write(&self, film: &Film, path: &Path) -> Result<(), ImageError> {
+        let width = film.width() as usize;
+        let height = film.height() as usize;
+        let pixel = |x: usize, y: usize| film.color(x as u32, y as u32);
+
+        let res = match self.precision {
+            ExrPrecision::Half => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                let color = pixel(x, y);
+                (f16::from_f64(color[0]), f16::from_f64(color[1]), f16::from_f64(color[2]))
+            }),
+            ExrPrecision::Float => exr::prelude::write_rgb_file(path, width, height, |x, y| {
+                let color = pixel(x, y);
+                (color[0] as f32, color[1] as f32, color[2] as f32)
+            }),
+        };
+
+        res?;
+
+        Ok(())
+    }
+}
+
+impl ImageWriter for HdrWriter {
+    fn write(&self, film: &Film, path: &Path) -> Result<(), ImageError> {
+        let width = film.width();
+        let mut file = BufWriter::new(File::create(path)?);
+
+        write!(file, "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n", film.height(), width)?;
+
+        for row in film.colors().chunks(width as usize) {
+            let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+
+            // run length encoded scanlines can only be this wide, other widths
+            // are written flat
+            if !(8..=0x7fff).contains(&width) {
+                for pixel in &rgbe {
+                    file.write_all(pixel)?;
+                }
+                continue;
+            }
+
+            file.write_all(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8])?;
+
+            // each component is stored separately, as literal runs of at most
+            // 128 bytes since the renders are rarely flat enough to repeat
+            for component in 0..4 {
+                let bytes: Vec<u8> = rgbe.iter().map(|pixel| pixel[component]).collect();
+
+                for chunk in bytes.chunks(128) {
+                    file.write_all(&[chunk.len() as u8])?;
+                    file.write_all(chunk)?;
+                }
+            }
+        }
+
+        file.flush()?;
+
+        Ok(())
+    }
+}
+
+/// Shared exponent encoding, each channel keeps 8 bits of mantissa relative
+/// to the brightest channel
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let brightest = color[0].max(color[1]).max(color[2]);
+
+    if brightest < 1e-32 {
+        return [0, 0, 0, 0];
+    }
+
+    // brightest = mantissa * 2^exponent with mantissa in [0.5, 1)
+    let mut exponent = brightest.log2().floor() as i32 + 1;
+    if brightest / 2f64.powi(exponent) >= 1. {
+        exponent += 1;
+    }
+
+    let scale = 256. / 2f64.powi(exponent);
+    let channel = |value: f64| (value.max(0.) * scale).min(255.) as u8;
+
+    [channel(color[0]), channel(color[1]), channel(color[2]), (exponent + 128) as u8]
+}
+
+/// Gamma corrects a linear channel and maps it to one of levels integer steps
+fn quantize(linear_value: f64, levels: f64) -> f64 {
+    (levels * clamp(0, Vec3::linear_to_gamma(linear_value), 1. - 1. / levels)).floor()
+}
@@ -0,0 +1,110 @@
+use crate::{
+    hit::Hittable,
+    onb::Onb,
+    random_f64,
+    Vec3,
+    PI,
+};
+
+type Point3 = Vec3;
+
+/// Distribution of random directions
+pub trait Pdf {
+    /// Probability density over solid angle of generate returning direction
+    fn value(&self, direction: &Vec3) -> f64;
+
+    fn generate(&self) -> Vec3;
+}
+
+/// Every direction equally likely
+pub struct SpherePdf;
+
+/// Directions around a normal, denser towards it by the cosine of the angle
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+/// Directions from origin towards a hittable as it is at time, using its
+/// pdf_value and random
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+    time: f64,
+}
+
+/// Picks either of two distributions with equal chance
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl Pdf for SpherePdf {
+    fn value(&self, _direction: &Vec3) -> f64 {
+        1. / (4. * PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        Vec3::random_unit_vec()
+    }
+}
+
+impl CosinePdf {
+    pub fn new(w: &Vec3) -> CosinePdf {
+        CosinePdf {
+            uvw: Onb::new(w),
+        }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value(&self, direction: &Vec3) -> f64 {
+        let cosine_theta = Vec3::dot(&direction.unit_vector(), &self.uvw.w);
+
+        f64::max(0., cosine_theta / PI)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.uvw.transform(&Vec3::random_cosine_direction())
+    }
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new(objects: &'a dyn Hittable, origin: Point3, time: f64) -> HittablePdf<'a> {
+        HittablePdf {
+            objects,
+            origin,
+            time,
+        }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        self.objects.pdf_value(&self.origin, direction, self.time)
+    }
+
+    fn generate(&self) -> Vec3 {
+        self.objects.random(&self.origin, self.time)
+    }
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new(p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> MixturePdf<'a> {
+        MixturePdf {
+            p: [p0, p1],
+        }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value(&self, direction: &Vec3) -> f64 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate(&self) -> Vec3 {
+        if random_f64() < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
@@ -0,0 +1,141 @@
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+
+use crate::Vec3;
+
+type Point3 = Vec3;
+
+const POINT_COUNT: usize = 256;
+
+/// Gradient noise, smooth random values in [-1, 1] that change over about a
+/// unit of distance. The same seed always gives the same noise
+pub struct Perlin {
+    randvec: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Perlin {
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        let randvec = (0..POINT_COUNT)
+            .map(|_| {
+                // unit vectors, picked evenly over the sphere
+                loop {
+                    let v = Vec3::new(
+                        rng.gen_range(-1. ..1.),
+                        rng.gen_range(-1. ..1.),
+                        rng.gen_range(-1. ..1.),
+                    );
+                    let length_squared = v.length_squared();
+
+                    if 1e-160 < length_squared && length_squared <= 1. {
+                        break v / length_squared.sqrt();
+                    }
+                }
+            })
+            .collect();
+
+        let mut generate_perm = || {
+            let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+            perm.shuffle(&mut rng);
+            perm
+        };
+
+        let perm_x = generate_perm();
+        let perm_y = generate_perm();
+        let perm_z = generate_perm();
+
+        Perlin {
+            randvec,
+            perm_x,
+            perm_y,
+            perm_z,
+        }
+    }
+
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let u = p.x() - p.x().floor();
+        let v = p.y() - p.y().floor();
+        let w = p.z() - p.z().floor();
+
+        let i = p.x().floor() as i64;
+        let j = p.y().floor() as i64;
+        let k = p.z().floor() as i64;
+
+        // gradients at the corners of the lattice cell around p
+        let mut c = [[[Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in c.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let idx = self.perm_x[((i + di as i64) & 255) as usize]
+                        ^ self.perm_y[((j + dj as i64) & 255) as usize]
+                        ^ self.perm_z[((k + dk as i64) & 255) as usize];
+
+                    *corner = self.randvec[idx];
+                }
+            }
+        }
+
+        perlin_interp(&c, u, v, w)
+    }
+
+    /// Sum of depth octaves of the noise's magnitude, each at twice the
+    /// frequency and half the weight of the last. Never negative
+    pub fn turbulence(&self, p: &Point3, depth: u32) -> f64 {
+        let mut accum = 0.;
+        let mut temp_p = *p;
+        let mut weight = 1.;
+
+        for _ in 0..depth {
+            accum += weight * self.noise(&temp_p).abs();
+            weight *= 0.5;
+            temp_p *= 2.;
+        }
+
+        accum
+    }
+
+    /// Fractional Brownian motion, a signed sum of octaves of the noise. Each
+    /// octave's frequency is multiplied by lacunarity and its weight by gain
+    pub fn fbm(&self, p: &Point3, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+        let mut accum = 0.;
+        let mut frequency = 1.;
+        let mut amplitude = 1.;
+
+        for _ in 0..octaves {
+            accum += amplitude * self.noise(&(*p * frequency));
+            frequency *= lacunarity;
+            amplitude *= gain;
+        }
+
+        accum
+    }
+}
+
+/// Trilinear blend of the corner gradients' dot products, with Hermite
+/// smoothing so there are no visible creases at cell boundaries
+fn perlin_interp(c: &[[[Vec3; 2]; 2]; 2], u: f64, v: f64, w: f64) -> f64 {
+    let uu = u * u * (3. - 2. * u);
+    let vv = v * v * (3. - 2. * v);
+    let ww = w * w * (3. - 2. * w);
+
+    let mut accum = 0.;
+
+    for (i, plane) in c.iter().enumerate() {
+        for (j, row) in plane.iter().enumerate() {
+            for (k, corner) in row.iter().enumerate() {
+                let (fi, fj, fk) = (i as f64, j as f64, k as f64);
+                let weight_v = Vec3::new(u - fi, v - fj, w - fk);
+
+                accum += (fi * uu + (1. - fi) * (1. - uu))
+                    * (fj * vv + (1. - fj) * (1. - vv))
+                    * (fk * ww + (1. - fk) * (1. - ww))
+                    * Vec3::dot(corner, &weight_v);
+            }
+        }
+    }
+
+    accum
+}
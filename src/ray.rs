@@ -1,17 +1,66 @@
-use crate::Vec3;
+use crate::{
+    spectrum::{self, PathColor},
+    Color,
+    Vec3,
+};
 
 type Point3 = Vec3;
 
 pub struct Ray {
     pub origin: Point3,
     pub direction: Point3,
+    /// Moment within the camera's shutter interval the ray exists at
+    pub time: f64,
+    /// Rgb unless the camera traces wavelengths
+    pub color: PathColor,
+    /// Share of each channel lost per unit of distance in the glass the ray
+    /// travels through, zero in the clear
+    pub absorption: Color,
 }
 
 impl Ray {
-    pub fn new(origin: Point3, direction: Point3) -> Ray {
-        Ray { origin, direction }
+    pub fn new(origin: Point3, direction: Point3, time: f64) -> Ray {
+        Ray {
+            origin,
+            direction,
+            time,
+            color: PathColor::Rgb,
+            absorption: Color::new(0, 0, 0),
+        }
     } 
 
+    /// The same ray carrying r's wavelengths and the glass it's in, for
+    /// passing them on to rays that continue a path
+    pub fn with_path_of(mut self, r: &Ray) -> Ray {
+        self.color = r.color;
+        self.absorption = r.absorption;
+        self
+    }
+
+    /// Share of each channel left after travelling up to t through the glass
+    /// the ray is in, at the path's wavelengths on spectral paths
+    pub fn interior_transmittance(&self, t: f64) -> Color {
+        if self.absorption.length_squared() <= 0. {
+            return Color::new(1, 1, 1);
+        }
+
+        let absorption = match self.color.wavelengths() {
+            Some(wavelengths) => spectrum::rgb_to_spectrum(&self.absorption, wavelengths),
+            None => self.absorption,
+        };
+        let distance = t * self.direction.length();
+
+        let mut res = Color::new(1, 1, 1);
+        for i in 0..3 {
+            // clear channels stay clear even for rays that never end
+            if absorption[i] > 0. {
+                res[i] = (-absorption[i] * distance).exp();
+            }
+        }
+
+        res
+    }
+
     pub fn at(&self, k: f64) -> Vec3 {
         self.origin + self.direction * k
     }
@@ -1,4 +1,31 @@
-use crate::vec3::Vec3;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::{
+    aabb::Aabb,
+    background::Background,
+    camera::Camera,
+    environment::EnvironmentMap,
+    hit::{Hittable, HittableList},
+    material::{Dielectric, DiffuseLight, Isotropic, Lambertian, Material, Metal},
+    medium::ConstantMedium,
+    obj::load_obj,
+    output::{BitDepth, ExrPrecision, OutputOptions},
+    spectrum::{Blackbody, ColorMode, RefractiveIndex},
+    shapes::{cuboid::Cuboid, quad::Quad, sphere::Sphere, triangle::Triangle},
+    texture::{CheckerTexture, ImageTexture, NoisePattern, NoiseTexture, SolidColor, Texture},
+    motion::Motion,
+    transform::{Instance, Transform},
+    vec3::Vec3,
+    volume::{DensityField, DensityGrid, HeterogeneousMedium, NoiseField},
+};
 
 type Point3 = Vec3;
 
@@ -61,3 +88,863 @@ impl SceneContext {
         }
     }
 }
+
+/// Camera and objects loaded from a scene description file
+pub struct Scene {
+    pub camera: Camera,
+    pub world: HittableList,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            SceneError::Parse { path, line, column, message } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { source, .. } => Some(source),
+            SceneError::Parse { .. } => None,
+        }
+    }
+}
+
+/// Reads a toml scene file, file paths in it are relative to the file
+pub fn load_scene<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+    let path = path.as_ref();
+    let source = fs::read_to_string(path).map_err(|source| SceneError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+
+    parse_scene(&source, path)
+}
+
+/// Parses the text of a scene file, path is used for error messages and
+/// resolving file paths
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let error_at = |offset: usize, message: String| {
+        let (line, column) = line_column(source, offset);
+
+        SceneError::Parse {
+            path: path.to_path_buf(),
+            line,
+            column,
+            message,
+        }
+    };
+
+    let desc: SceneDesc = toml::from_str(source).map_err(|e| {
+        let offset = e.span().map(|span| span.start).unwrap_or(0);
+        error_at(offset, e.message().to_string())
+    })?;
+
+    let mut camera = Camera::default();
+    desc.camera.apply(&mut camera);
+    desc.output.apply(&mut camera.output);
+
+    if let Some(background) = desc.background {
+        let offset = background.span().start;
+
+        camera.background = match background.into_inner() {
+            BackgroundDesc::Solid { color } => Background::Solid(to_vec3(color)),
+            BackgroundDesc::Gradient { bottom, top } => Background::gradient(to_vec3(bottom), to_vec3(top)),
+            BackgroundDesc::Environment { path: map_path, rotation, intensity } => {
+                let map_path = path.parent().unwrap_or(Path::new("")).join(map_path);
+                let mut map = EnvironmentMap::load(&map_path)
+                    .map_err(|e| error_at(offset, format!("{}: {}", map_path.display(), e)))?;
+                map.rotation = rotation;
+                map.intensity = intensity;
+
+                Background::Environment(map)
+            }
+        };
+    }
+
+    let mut textures: HashMap<String, Rc<dyn Texture>> = HashMap::new();
+    for (name, texture) in desc.textures {
+        let offset = texture.span().start;
+        let texture: Rc<dyn Texture> = match texture.into_inner() {
+            TextureDesc::Solid { color } => Rc::from(SolidColor::new(to_vec3(color))),
+            TextureDesc::Checker { scale, even, odd } => {
+                Rc::from(CheckerTexture::from_colors(scale, to_vec3(even), to_vec3(odd)))
+            }
+            TextureDesc::Image { path: image_path } => {
+                let image_path = path.parent().unwrap_or(Path::new("")).join(image_path);
+                let image = ImageTexture::load(&image_path)
+                    .map_err(|e| error_at(offset, format!("{}: {}", image_path.display(), e)))?;
+                Rc::from(image)
+            }
+            TextureDesc::Noise { pattern, scale, seed, octaves, low, high } => {
+                let pattern = match pattern {
+                    NoisePatternDesc::Perlin => NoisePattern::Perlin,
+                    NoisePatternDesc::Turbulence => NoisePattern::Turbulence { depth: octaves },
+                    NoisePatternDesc::Fbm => NoisePattern::Fbm { octaves },
+                    NoisePatternDesc::Marble => NoisePattern::Marble { depth: octaves },
+                    NoisePatternDesc::Wood => NoisePattern::Wood { depth: octaves },
+                };
+
+                let mut noise = NoiseTexture::new(seed, pattern, scale);
+                noise.low = to_vec3(low);
+                noise.high = to_vec3(high);
+                Rc::from(noise)
+            }
+        };
+        textures.insert(name, texture);
+    }
+
+    let mut materials: HashMap<String, Rc<dyn Material>> = HashMap::new();
+    for (name, material) in desc.materials {
+        let offset = material.span().start;
+        let material = material
+            .into_inner()
+            .build(&textures)
+            .map_err(|message| error_at(offset, message))?;
+        materials.insert(name, material);
+    }
+
+    let mut world = HittableList::new();
+
+    for shape in desc.shapes {
+        let offset = shape.span().start;
+        let find_material = |name: &str| {
+            materials
+                .get(name)
+                .cloned()
+                .ok_or_else(|| error_at(offset, format!("unknown material '{}'", name)))
+        };
+
+        let ShapeEntry { shape, transform, mut motion, medium } = shape.into_inner();
+
+        let object: Rc<dyn Hittable> = match shape {
+            // spheres can move by themselves, without the cost of an instance
+            ShapeDesc::Sphere { center, radius, material } if motion.is_some() && transform.is_empty() => {
+                let center = motion
+                    .take()
+                    .unwrap()
+                    .build(to_vec3(center))
+                    .map_err(|message| error_at(offset, message))?;
+
+                Rc::from(Sphere::moving(center, radius, find_material(&material)?))
+            }
+            ShapeDesc::Sphere { center, radius, material } => {
+                Rc::from(Sphere::new(to_vec3(center), radius, find_material(&material)?))
+            }
+            ShapeDesc::Triangle { vertices, normals, backface_culling, material } => {
+                let [a, b, c] = vertices.map(to_vec3);
+                let mut triangle = match normals {
+                    Some(normals) => Triangle::with_normals(a, b, c, normals.map(to_vec3), find_material(&material)?),
+                    None => Triangle::new(a, b, c, find_material(&material)?),
+                };
+                triangle.backface_culling = backface_culling;
+
+                Rc::from(triangle)
+            }
+            ShapeDesc::Quad { corner, u, v, material } => {
+                Rc::from(Quad::new(to_vec3(corner), to_vec3(u), to_vec3(v), find_material(&material)?))
+            }
+            ShapeDesc::Box { min, max, material } => {
+                let materials = material.build(find_material)?;
+                Rc::from(Cuboid::with_face_materials(to_vec3(min), to_vec3(max), materials))
+            }
+            ShapeDesc::OrientedBox { corner, edges, material } => {
+                let materials = material.build(find_material)?;
+                Rc::from(Cuboid::oriented(to_vec3(corner), edges.map(to_vec3), materials))
+            }
+            ShapeDesc::Mesh { path: mesh_path, backface_culling, material } => {
+                let mesh_path = path.parent().unwrap_or(Path::new("")).join(mesh_path);
+                let mut mesh = load_obj(mesh_path, find_material(&material)?)
+                    .map_err(|e| error_at(offset, e.to_string()))?;
+                mesh.backface_culling = backface_culling;
+
+                Rc::from(mesh)
+            }
+            ShapeDesc::Volume { min, max, density, absorption, scattering, emission } => {
+                if absorption.iter().chain(&scattering).chain(&emission).any(|c| *c < 0.) {
+                    return Err(error_at(offset, String::from("volume coefficients can't be negative")));
+                }
+
+                let field: Rc<dyn DensityField> = match density {
+                    DensityDesc::Grid { path: grid_path } => {
+                        let grid_path = path.parent().unwrap_or(Path::new("")).join(grid_path);
+                        let mut grid = DensityGrid::load(&grid_path).map_err(|e| error_at(offset, e.to_string()))?;
+                        grid.bounds = Aabb::new(to_vec3(min), to_vec3(max));
+
+                        Rc::from(grid)
+                    }
+                    DensityDesc::Noise { seed, scale, octaves, threshold } => {
+                        let mut noise = NoiseField::new(seed, scale);
+                        noise.octaves = octaves;
+                        noise.threshold = threshold;
+
+                        Rc::from(noise)
+                    }
+                };
+
+                // only the box's shape matters, its material is never hit
+                let boundary = Rc::from(Cuboid::new(
+                    to_vec3(min),
+                    to_vec3(max),
+                    Rc::from(Isotropic::new(Vec3::new(1, 1, 1))),
+                ));
+
+                let mut volume = HeterogeneousMedium::new(boundary, field);
+                volume.absorption = to_vec3(absorption);
+                volume.scattering = to_vec3(scattering);
+                volume.emission = to_vec3(emission);
+
+                Rc::from(volume)
+            }
+        };
+
+        let object: Rc<dyn Hittable> = if transform.is_empty() && motion.is_none() {
+            object
+        } else {
+            let mut combined = Transform::identity();
+            for step in transform {
+                combined = combined.then(&step.build().map_err(|message| error_at(offset, message))?);
+            }
+
+            let motion = match motion {
+                Some(motion) => motion.build(Vec3::default()).map_err(|message| error_at(offset, message))?,
+                None => Motion::fixed(Vec3::default()),
+            };
+
+            Rc::from(Instance::moving(object, combined, motion))
+        };
+
+        let object: Rc<dyn Hittable> = match medium {
+            Some(MediumDesc { density, material }) => {
+                if density <= 0. {
+                    return Err(error_at(offset, String::from("medium density must be positive")));
+                }
+
+                Rc::from(ConstantMedium::with_phase_function(object, density, find_material(&material)?))
+            }
+            None => object,
+        };
+
+        world.add(object);
+    }
+
+    Ok(Scene {
+        camera,
+        world,
+    })
+}
+
+/// 1 based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let column = before[line_start..].chars().count() + 1;
+
+    (line, column)
+}
+
+fn to_vec3(v: [f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    background: Option<Spanned<BackgroundDesc>>,
+    #[serde(default)]
+    output: OutputDesc,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDesc>>,
+    #[serde(default)]
+    shapes: Vec<Spanned<ShapeEntry>>,
+}
+
+/// Any field left out keeps the Camera default
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f32>,
+    image_width: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_ray_bounce_depth: Option<u32>,
+    vfov: Option<u32>,
+    vup: Option<[f64; 3]>,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    color_mode: Option<ColorModeDesc>,
+}
+
+impl CameraDesc {
+    fn apply(self, camera: &mut Camera) {
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(image_width) = self.image_width {
+            camera.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_ray_bounce_depth) = self.max_ray_bounce_depth {
+            camera.max_ray_bounce_depth = max_ray_bounce_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(vup) = self.vup {
+            camera.vup = to_vec3(vup);
+        }
+        if let Some(look_from) = self.look_from {
+            camera.look_from = to_vec3(look_from);
+        }
+        if let Some(look_at) = self.look_at {
+            camera.look_at = to_vec3(look_at);
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(shutter_open) = self.shutter_open {
+            camera.shutter_open = shutter_open;
+        }
+        if let Some(shutter_close) = self.shutter_close {
+            camera.shutter_close = shutter_close;
+        }
+        if let Some(color_mode) = self.color_mode {
+            camera.color_mode = match color_mode {
+                ColorModeDesc::Rgb => ColorMode::Rgb,
+                ColorModeDesc::SingleWavelength => ColorMode::SingleWavelength,
+                ColorModeDesc::Spectral => ColorMode::Spectral,
+            };
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ColorModeDesc {
+    Rgb,
+    SingleWavelength,
+    Spectral,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct OutputDesc {
+    bit_depth: Option<BitDepthDesc>,
+    exr_precision: Option<ExrPrecisionDesc>,
+}
+
+impl OutputDesc {
+    fn apply(self, output: &mut OutputOptions) {
+        if let Some(bit_depth) = self.bit_depth {
+            output.bit_depth = match bit_depth {
+                BitDepthDesc::Eight => BitDepth::Eight,
+                BitDepthDesc::Sixteen => BitDepth::Sixteen,
+            };
+        }
+        if let Some(exr_precision) = self.exr_precision {
+            output.exr_precision = match exr_precision {
+                ExrPrecisionDesc::Half => ExrPrecision::Half,
+                ExrPrecisionDesc::Float => ExrPrecision::Float,
+            };
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ExrPrecisionDesc {
+    Half,
+    Float,
+}
+
+/// Written as a plain 8 or 16 in the file
+#[derive(Deserialize)]
+#[serde(try_from = "u8")]
+enum BitDepthDesc {
+    Eight,
+    Sixteen,
+}
+
+impl TryFrom<u8> for BitDepthDesc {
+    type Error = String;
+
+    fn try_from(bits: u8) -> Result<Self, Self::Error> {
+        match bits {
+            8 => Ok(BitDepthDesc::Eight),
+            16 => Ok(BitDepthDesc::Sixteen),
+            _ => Err(format!("bit_depth must be 8 or 16, got {}", bits)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum BackgroundDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    Gradient {
+        bottom: [f64; 3],
+        top: [f64; 3],
+    },
+    /// Equirectangular .hdr or .exr file
+    Environment {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+}
+
+fn default_intensity() -> f64 {
+    1.
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid {
+        color: [f64; 3],
+    },
+    /// 3D checker pattern, scale is the size of each cube
+    Checker {
+        scale: f64,
+        even: [f64; 3],
+        odd: [f64; 3],
+    },
+    /// png or jpeg file
+    Image {
+        path: PathBuf,
+    },
+    /// Blends from low to high by a Perlin noise pattern, octaves is also
+    /// the depth of turbulence based patterns
+    Noise {
+        pattern: NoisePatternDesc,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_octaves")]
+        octaves: u32,
+        #[serde(default)]
+        low: [f64; 3],
+        #[serde(default = "default_high")]
+        high: [f64; 3],
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum NoisePatternDesc {
+    Perlin,
+    Turbulence,
+    Fbm,
+    Marble,
+    Wood,
+}
+
+fn default_noise_scale() -> f64 {
+    1.
+}
+
+fn default_octaves() -> u32 {
+    7
+}
+
+fn default_high() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+/// A plain number, or coefficients of a formula in wavelength for glass
+/// that disperses light
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RefractiveIndexDesc {
+    Constant(f64),
+    Formula(RefractiveIndexFormula),
+}
+
+/// Wavelengths are in micrometres
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum RefractiveIndexFormula {
+    Cauchy {
+        a: f64,
+        b: f64,
+    },
+    Sellmeier {
+        b: [f64; 3],
+        c: [f64; 3],
+    },
+}
+
+impl RefractiveIndexDesc {
+    fn build(self) -> RefractiveIndex {
+        match self {
+            RefractiveIndexDesc::Constant(n) => RefractiveIndex::Constant(n),
+            RefractiveIndexDesc::Formula(RefractiveIndexFormula::Cauchy { a, b }) => RefractiveIndex::Cauchy { a, b },
+            RefractiveIndexDesc::Formula(RefractiveIndexFormula::Sellmeier { b, c }) => {
+                RefractiveIndex::Sellmeier { b, c }
+            }
+        }
+    }
+}
+
+fn default_absorption_distance() -> f64 {
+    1.
+}
+
+/// Either a color or the name of a texture
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AlbedoDesc {
+    Color([f64; 3]),
+    Texture(String),
+}
+
+impl AlbedoDesc {
+    fn build(self, textures: &HashMap<String, Rc<dyn Texture>>) -> Result<Rc<dyn Texture>, String> {
+        match self {
+            AlbedoDesc::Color(color) => Ok(Rc::from(SolidColor::new(to_vec3(color)))),
+            AlbedoDesc::Texture(name) => textures
+                .get(&name)
+                .cloned()
+                .ok_or_else(|| format!("unknown texture '{}'", name)),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: AlbedoDesc,
+    },
+    Metal {
+        albedo: AlbedoDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    /// Tinted to absorption after travelling absorption_distance inside,
+    /// clear without it
+    Dielectric {
+        refraction_index: RefractiveIndexDesc,
+        absorption: Option<[f64; 3]>,
+        #[serde(default = "default_absorption_distance")]
+        absorption_distance: f64,
+    },
+    /// Gives off emit, or glows like a blackbody at temperature in kelvin
+    /// with a luminance of intensity
+    DiffuseLight {
+        emit: Option<[f64; 3]>,
+        temperature: Option<f64>,
+        #[serde(default = "default_intensity")]
+        intensity: f64,
+    },
+    /// Phase function for volumes, scatters evenly in every direction
+    Isotropic {
+        albedo: AlbedoDesc,
+    },
+}
+
+impl MaterialDesc {
+    fn build(self, textures: &HashMap<String, Rc<dyn Texture>>) -> Result<Rc<dyn Material>, String> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => Rc::from(Lambertian::from_texture(albedo.build(textures)?)),
+            MaterialDesc::Metal { albedo, fuzz } => Rc::from(Metal::from_texture(albedo.build(textures)?, fuzz)),
+            MaterialDesc::Dielectric { refraction_index, absorption, absorption_distance } => {
+                let refraction_index = refraction_index.build();
+
+                match absorption {
+                    Some(color) => {
+                        if absorption_distance <= 0. {
+                            return Err(String::from("absorption_distance must be positive"));
+                        }
+
+                        Rc::from(Dielectric::with_absorption(refraction_index, to_vec3(color), absorption_distance))
+                    }
+                    None => Rc::from(Dielectric::new(refraction_index)),
+                }
+            }
+            MaterialDesc::DiffuseLight { emit, temperature, intensity } => match (emit, temperature) {
+                (Some(emit), None) => Rc::from(DiffuseLight::new(to_vec3(emit))),
+                (None, Some(temperature)) if Blackbody::is_visible(temperature) => {
+                    Rc::from(DiffuseLight::blackbody(temperature, intensity))
+                }
+                (None, Some(temperature)) if temperature > 0. => {
+                    return Err(format!("temperature {temperature} is too low to give off visible light"))
+                }
+                (None, Some(_)) => return Err(String::from("temperature must be positive")),
+                _ => return Err(String::from("diffuse_light needs one of emit or temperature")),
+            },
+            MaterialDesc::Isotropic { albedo } => Rc::from(Isotropic::from_texture(albedo.build(textures)?)),
+        })
+    }
+}
+
+/// A shape and the transforms placing it, applied in the order listed,
+/// followed by its motion and optionally filled with a medium
+#[derive(Deserialize)]
+struct ShapeEntry {
+    #[serde(flatten)]
+    shape: ShapeDesc,
+    #[serde(default)]
+    transform: Vec<TransformDesc>,
+    motion: Option<MotionDesc>,
+    medium: Option<MediumDesc>,
+}
+
+/// Fills the shape with a constant density volume scattering by material,
+/// the shape's own material is then unused. The shape must be convex
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MediumDesc {
+    density: f64,
+    material: String,
+}
+
+/// Movement over the shutter interval, as offsets from where the shape is
+/// placed
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MotionDesc {
+    /// Moves by offset between time 0 and 1
+    Linear {
+        offset: [f64; 3],
+    },
+    Keyframes {
+        keys: Vec<KeyframeDesc>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f64,
+    offset: [f64; 3],
+}
+
+impl MotionDesc {
+    /// Motion of start moved by the offsets
+    fn build(self, start: Point3) -> Result<Motion, String> {
+        match self {
+            MotionDesc::Linear { offset } => Ok(Motion::linear(start, start + to_vec3(offset))),
+            MotionDesc::Keyframes { keys } => {
+                if keys.is_empty() {
+                    return Err(String::from("motion needs at least one keyframe"));
+                }
+
+                Ok(Motion::keyframes(
+                    keys.into_iter()
+                        .map(|key| (key.time, start + to_vec3(key.offset)))
+                        .collect(),
+                ))
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate([f64; 3]),
+    /// A single number scales evenly in every direction
+    Scale(ScaleDesc),
+    /// Rotations are in degrees
+    RotateX(f64),
+    RotateY(f64),
+    RotateZ(f64),
+    Rotate {
+        axis: [f64; 3],
+        angle: f64,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    PerAxis([f64; 3]),
+}
+
+impl TransformDesc {
+    fn build(self) -> Result<Transform, String> {
+        Ok(match self {
+            TransformDesc::Translate(offset) => Transform::translate(to_vec3(offset)),
+            TransformDesc::Scale(scale) => {
+                let factors = match scale {
+                    ScaleDesc::Uniform(factor) => [factor; 3],
+                    ScaleDesc::PerAxis(factors) => factors,
+                };
+
+                if factors.contains(&0.) {
+                    return Err(String::from("scale factors can't be zero"));
+                }
+
+                Transform::scale(to_vec3(factors))
+            }
+            TransformDesc::RotateX(angle) => Transform::rotate_x(angle),
+            TransformDesc::RotateY(angle) => Transform::rotate_y(angle),
+            TransformDesc::RotateZ(angle) => Transform::rotate_z(angle),
+            TransformDesc::Rotate { axis, angle } => {
+                if axis == [0.; 3] {
+                    return Err(String::from("rotation axis can't be zero"));
+                }
+
+                Transform::rotate(to_vec3(axis), angle)
+            }
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        #[serde(default)]
+        backface_culling: bool,
+        material: String,
+    },
+    /// Parallelogram from corner spanned by the edges u and v
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    /// Axis aligned box between two corners
+    Box {
+        min: [f64; 3],
+        max: [f64; 3],
+        material: FaceMaterialsDesc,
+    },
+    /// Box from corner spanned by three edges
+    OrientedBox {
+        corner: [f64; 3],
+        edges: [[f64; 3]; 3],
+        material: FaceMaterialsDesc,
+    },
+    /// Wavefront obj file, material is used for faces without one of their own
+    Mesh {
+        path: PathBuf,
+        #[serde(default)]
+        backface_culling: bool,
+        material: String,
+    },
+    /// Volume of varying density filling the box between two corners, the
+    /// coefficients are per unit density
+    Volume {
+        min: [f64; 3],
+        max: [f64; 3],
+        density: DensityDesc,
+        #[serde(default)]
+        absorption: [f64; 3],
+        #[serde(default = "default_scattering")]
+        scattering: [f64; 3],
+        #[serde(default)]
+        emission: [f64; 3],
+    },
+}
+
+/// Where a volume's density comes from
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum DensityDesc {
+    /// Dense grid file stretched over the volume's box
+    Grid {
+        path: PathBuf,
+    },
+    /// fBm noise in the volume's own space
+    Noise {
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "default_noise_scale")]
+        scale: f64,
+        #[serde(default = "default_volume_octaves")]
+        octaves: u32,
+        #[serde(default)]
+        threshold: f64,
+    },
+}
+
+fn default_scattering() -> [f64; 3] {
+    [1., 1., 1.]
+}
+
+fn default_volume_octaves() -> u32 {
+    5
+}
+
+/// One material for the whole box or one per face, ordered -x, +x, -y, +y,
+/// -z, +z
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FaceMaterialsDesc {
+    Single(String),
+    PerFace([String; 6]),
+}
+
+impl FaceMaterialsDesc {
+    fn build<F>(self, find_material: F) -> Result<[Rc<dyn Material>; 6], SceneError>
+    where
+        F: Fn(&str) -> Result<Rc<dyn Material>, SceneError>,
+    {
+        match self {
+            FaceMaterialsDesc::Single(name) => {
+                let material = find_material(&name)?;
+                Ok(std::array::from_fn(|_| material.clone()))
+            }
+            FaceMaterialsDesc::PerFace(names) => {
+                let [a, b, c, d, e, f] = names;
+                Ok([
+                    find_material(&a)?,
+                    find_material(&b)?,
+                    find_material(&c)?,
+                    find_material(&d)?,
+                    find_material(&e)?,
+                    find_material(&f)?,
+                ])
+            }
+        }
+    }
+}
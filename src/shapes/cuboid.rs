@@ -0,0 +1,123 @@
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    random_f64,
+    shapes::quad::Quad,
+    Ray,
+    Vec3,
+    material::Material,
+};
+
+type Point3 = Vec3;
+
+/// Six sided box made of quads, faces are ordered -x, +x, -y, +y, -z, +z.
+/// For oriented boxes x, y and z are the three edges instead of the axes
+pub struct Cuboid {
+    faces: [Quad; 6],
+    /// Indices of the faces that emit, the ones light is sampled over
+    lights: Vec<usize>,
+}
+
+impl Cuboid {
+    /// Axis aligned box with a and b as opposite corners
+    pub fn new(a: Point3, b: Point3, material: Rc<dyn Material>) -> Cuboid {
+        Cuboid::with_face_materials(a, b, std::array::from_fn(|_| material.clone()))
+    }
+
+    pub fn with_face_materials(a: Point3, b: Point3, materials: [Rc<dyn Material>; 6]) -> Cuboid {
+        let min = Point3::new(a.x().min(*b.x()), a.y().min(*b.y()), a.z().min(*b.z()));
+        let max = Point3::new(a.x().max(*b.x()), a.y().max(*b.y()), a.z().max(*b.z()));
+        let size = max - min;
+
+        let edges = [
+            Vec3::new(*size.x(), 0, 0),
+            Vec3::new(0, *size.y(), 0),
+            Vec3::new(0, 0, *size.z()),
+        ];
+
+        Cuboid::oriented(min, edges, materials)
+    }
+
+    /// Parallelepiped from corner spanned by the three edges, so it can be
+    /// rotated or sheared
+    pub fn oriented(corner: Point3, edges: [Vec3; 3], materials: [Rc<dyn Material>; 6]) -> Cuboid {
+        let [x, y, mut z] = edges;
+        let mut corner = corner;
+        let mut materials = materials;
+
+        // faces point outwards only when the edges are right handed, flipping
+        // z gives the same box from the opposite corner
+        if Vec3::dot(&x, &Vec3::cross(&y, &z)) < 0. {
+            corner += z;
+            z = -z;
+            materials.swap(4, 5);
+        }
+
+        let [min_x, max_x, min_y, max_y, min_z, max_z] = materials;
+
+        let faces = [
+            Quad::new(corner, z, y, min_x),
+            Quad::new(corner + x, y, z, max_x),
+            Quad::new(corner, x, z, min_y),
+            Quad::new(corner + y, z, x, max_y),
+            Quad::new(corner, y, x, min_z),
+            Quad::new(corner + z, x, y, max_z),
+        ];
+        let lights = (0..6).filter(|i| faces[*i].is_emissive()).collect();
+
+        Cuboid { faces, lights }
+    }
+
+    pub fn faces(&self) -> &[Quad; 6] {
+        &self.faces
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let mut closest = None;
+        let mut closest_t = ray_tmax;
+
+        for face in &self.faces {
+            if let Some(rec) = face.hit(r, ray_tmin, closest_t) {
+                closest_t = rec.t;
+                closest = Some(rec);
+            }
+        }
+
+        closest
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.faces
+            .iter()
+            .fold(Aabb::empty(), |bbox, face| Aabb::surrounding(&bbox, &face.bounding_box()))
+    }
+
+    /// Light is sampled over the faces that emit
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        if self.lights.is_empty() {
+            return 0.;
+        }
+
+        let sum: f64 = self.lights.iter().map(|i| self.faces[*i].pdf_value(origin, direction, time)).sum();
+
+        sum / self.lights.len() as f64
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        if self.lights.is_empty() {
+            return Vec3::new(1, 0, 0);
+        }
+
+        let idx = ((random_f64() * self.lights.len() as f64) as usize).min(self.lights.len() - 1);
+
+        self.faces[self.lights[idx]].random(origin, time)
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.lights.is_empty()
+    }
+}
@@ -0,0 +1,149 @@
+use std::ops::Range;
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    bvh::{build_flat_nodes, flat_nodes_bounding_box, traverse_flat_nodes, FlatBvhNode, SplitMethod},
+    hit::{HitRecord, Hittable},
+    shapes::triangle,
+    Ray,
+    Vec3,
+    material::Material,
+};
+
+type Point3 = Vec3;
+
+/// Triangle of a mesh, all fields are indices into the mesh's shared storage
+pub struct MeshFace {
+    pub positions: [usize; 3],
+    pub normals: Option<[usize; 3]>,
+    pub texcoords: Option<[usize; 3]>,
+    pub material: usize,
+}
+
+/// Named run of consecutive faces
+pub struct MeshGroup {
+    pub name: String,
+    pub faces: Range<usize>,
+}
+
+/// Triangle mesh with vertex data shared between faces, faces are found
+/// through the mesh's own bvh
+pub struct Mesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vec3>,
+    texcoords: Vec<(f64, f64)>,
+    faces: Vec<MeshFace>,
+    groups: Vec<MeshGroup>,
+    materials: Vec<Rc<dyn Material>>,
+    nodes: Vec<FlatBvhNode>,
+    /// Face indices in the order the bvh leaves refer to them
+    face_order: Vec<usize>,
+    /// Ignores hits from behind faces
+    pub backface_culling: bool,
+}
+
+impl Mesh {
+    /// Panics if a face refers to data that doesn't exist
+    pub fn new(
+        positions: Vec<Point3>,
+        normals: Vec<Vec3>,
+        texcoords: Vec<(f64, f64)>,
+        faces: Vec<MeshFace>,
+        groups: Vec<MeshGroup>,
+        materials: Vec<Rc<dyn Material>>,
+    ) -> Mesh {
+        for face in &faces {
+            assert!(face.positions.iter().all(|&i| i < positions.len()), "face position out of range");
+            assert!(face.normals.iter().flatten().all(|&i| i < normals.len()), "face normal out of range");
+            assert!(face.texcoords.iter().flatten().all(|&i| i < texcoords.len()), "face texcoord out of range");
+            assert!(face.material < materials.len(), "face material out of range");
+        }
+
+        let boxes: Vec<Aabb> = faces
+            .iter()
+            .map(|face| {
+                let [a, b, c] = face.positions.map(|i| positions[i]);
+                Aabb::surrounding(&Aabb::new(a, b), &Aabb::new(a, c))
+            })
+            .collect();
+
+        let (nodes, face_order) = build_flat_nodes(&boxes, SplitMethod::Sah);
+
+        Mesh {
+            positions,
+            normals,
+            texcoords,
+            faces,
+            groups,
+            materials,
+            nodes,
+            face_order,
+            backface_culling: false,
+        }
+    }
+
+    pub fn positions(&self) -> &[Point3] {
+        &self.positions
+    }
+
+    pub fn normals(&self) -> &[Vec3] {
+        &self.normals
+    }
+
+    pub fn texcoords(&self) -> &[(f64, f64)] {
+        &self.texcoords
+    }
+
+    pub fn faces(&self) -> &[MeshFace] {
+        &self.faces
+    }
+
+    pub fn groups(&self) -> &[MeshGroup] {
+        &self.groups
+    }
+
+    pub fn materials(&self) -> &[Rc<dyn Material>] {
+        &self.materials
+    }
+
+    fn face_vertices(&self, face: &MeshFace) -> [Point3; 3] {
+        face.positions.map(|i| self.positions[i])
+    }
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        traverse_flat_nodes(&self.nodes, r, ray_tmin, ray_tmax, |i, tmax| {
+            let face = &self.faces[self.face_order[i]];
+            let vertices = self.face_vertices(face);
+
+            let (t, u, v) = triangle::intersect(&vertices, r, ray_tmin, tmax, self.backface_culling)?;
+
+            let normals = face.normals.map(|indices| indices.map(|i| self.normals[i]));
+
+            let mut rec = triangle::hit_record(
+                &vertices,
+                normals.as_ref(),
+                r,
+                t,
+                u,
+                v,
+                self.materials[face.material].clone(),
+            );
+
+            if let Some(texcoords) = face.texcoords {
+                let [ta, tb, tc] = texcoords.map(|i| self.texcoords[i]);
+
+                rec.u = ta.0 * (1. - u - v) + tb.0 * u + tc.0 * v;
+                rec.v = ta.1 * (1. - u - v) + tb.1 * u + tc.1 * v;
+            }
+
+            Some(rec)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        flat_nodes_bounding_box(&self.nodes)
+    }
+}
@@ -0,0 +1,119 @@
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    random_f64,
+    Ray,
+    Vec3,
+    material::Material,
+    INFINITY,
+};
+
+type Point3 = Vec3;
+
+/// Parallelogram with corners q, q + u, q + v and q + u + v. The front face
+/// is the side u x v points to
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    material: Rc<dyn Material>,
+    normal: Vec3,
+    /// Plane is every point p with dot(normal, p) = d
+    d: f64,
+    /// Turns a point on the plane into its u and v coordinates
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Rc<dyn Material>) -> Quad {
+        let n = Vec3::cross(&u, &v);
+        let normal = n.unit_vector();
+
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d: Vec3::dot(&normal, &q),
+            w: n / Vec3::dot(&n, &n),
+            area: n.length(),
+        }
+    }
+
+    pub fn corner(&self) -> Point3 {
+        self.q
+    }
+
+    /// The two edges leaving the corner
+    pub fn edges(&self) -> (Vec3, Vec3) {
+        (self.u, self.v)
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let denom = Vec3::dot(&self.normal, &r.direction);
+
+        // parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+
+        let t = (self.d - Vec3::dot(&self.normal, &r.origin)) / denom;
+        if t <= ray_tmin || ray_tmax <= t {
+            return None;
+        }
+
+        // position on the plane in terms of the edges
+        let intersection = r.at(t);
+        let planar_hitpt = intersection - self.q;
+        let alpha = Vec3::dot(&self.w, &Vec3::cross(&planar_hitpt, &self.v));
+        let beta = Vec3::dot(&self.w, &Vec3::cross(&self.u, &planar_hitpt));
+
+        if !(0. ..=1.).contains(&alpha) || !(0. ..=1.).contains(&beta) {
+            return None;
+        }
+
+        let mut rec = HitRecord::new(intersection, self.normal, t, r, self.material.clone());
+        (rec.u, rec.v) = (alpha, beta);
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonal1 = Aabb::new(self.q, self.q + self.u + self.v);
+        let diagonal2 = Aabb::new(self.q + self.u, self.q + self.v);
+
+        Aabb::surrounding(&diagonal1, &diagonal2)
+    }
+
+    /// Points are picked evenly over the quad
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let Some(rec) = self.hit(&Ray::new(*origin, *direction, time), 0.001, INFINITY) else {
+            return 0.;
+        };
+
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = Vec3::dot(direction, &self.normal).abs() / direction.length();
+
+        if self.area <= 0. || cosine <= 0. {
+            return 0.;
+        }
+
+        distance_squared / (cosine * self.area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let p = self.q + self.u * random_f64() + self.v * random_f64();
+
+        p - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
@@ -1,39 +1,70 @@
 use std::rc::Rc;
 
 use crate::{
+    aabb::Aabb,
     hit::{HitRecord, Hittable},
+    motion::Motion,
+    onb::Onb,
+    random_f64,
     Ray,
     Vec3,
     material::Material,
+    INFINITY,
+    PI,
 };
 
 type Point3 = Vec3;
 
 pub struct Sphere {
-    pub center: Point3,
+    motion: Motion,
     pub radius: f64,
     material: Rc<dyn Material>,
 }
 
 impl Sphere {
     pub fn new<T: Into<f64>>(center: Point3, radius: T, material: Rc<dyn Material>) -> Sphere {
+        Sphere::moving(Motion::fixed(center), radius, material)
+    }
+
+    /// Sphere whose center follows the motion, for motion blur
+    pub fn moving<T: Into<f64>>(motion: Motion, radius: T, material: Rc<dyn Material>) -> Sphere {
         let mut r = radius.into();
         if r < 0. {
             r = 0.
         }
 
         Sphere {
-            center,
+            motion,
             radius: r,
             material,
         }
     }
+
+    /// Where the center is at time, fixed spheres give the same point always
+    pub fn center(&self, time: f64) -> Point3 {
+        self.motion.at(time)
+    }
+
+    pub fn motion(&self) -> &Motion {
+        &self.motion
+    }
+}
+
+/// Longitude and latitude of a point on the unit sphere, both in [0, 1].
+/// u starts at -x going round towards +z, v goes from -y to +y
+fn sphere_uv(p: &Point3) -> (f64, f64) {
+    let theta = (-p.y()).clamp(-1., 1.).acos();
+    let phi = (-p.z()).atan2(*p.x()) + PI;
+
+    (phi / (2. * PI), theta / PI)
 }
 
 impl Hittable for Sphere {
     fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let center = self.center(r.time);
+
         // math for this in section 6.2
-        let oc = self.center - r.origin;
+        let oc = center - r.origin;
         let a = r.direction.length_squared();
         let h = Vec3::dot(&r.direction, &oc);
         let c = oc.length_squared() - self.radius * self.radius;
@@ -64,8 +95,69 @@ impl Hittable for Sphere {
 
         let t = root;
         let point = r.at(root);
-        let normal = (point - self.center) / self.radius;
+        let normal = (point - center) / self.radius;
+
+        let mut rec = HitRecord::new(point, normal, t, r, self.material.clone());
+        (rec.u, rec.v) = sphere_uv(&normal);
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let radius_vec = Vec3::new(self.radius, self.radius, self.radius);
+
+        // covers the sphere at every keyframe and so everywhere in between
+        self.motion
+            .positions()
+            .map(|center| Aabb::new(center - radius_vec, center + radius_vec))
+            .fold(Aabb::empty(), |bbox, key_box| Aabb::surrounding(&bbox, &key_box))
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let Some(rec) = self.hit(&Ray::new(*origin, *direction, time), 0.001, INFINITY) else {
+            return 0.;
+        };
+
+        let distance_squared = (self.center(time) - *origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            // inside, points are picked evenly over the surface
+            let to_point = rec.point - *origin;
+            let cosine = Vec3::dot(&rec.normal, &to_point.unit_vector()).abs();
+            let area = 4. * PI * radius_squared;
+
+            return to_point.length_squared() / (cosine * area);
+        }
+
+        // outside, directions are picked evenly over the cone the sphere fills
+        let cos_theta_max = (1. - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2. * PI * (1. - cos_theta_max);
+
+        1. / solid_angle
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        let center = self.center(time);
+        let direction = center - *origin;
+        let distance_squared = direction.length_squared();
+        let radius_squared = self.radius * self.radius;
+
+        if distance_squared <= radius_squared {
+            return center + Vec3::random_unit_vec() * self.radius - *origin;
+        }
+
+        let r1 = random_f64();
+        let r2 = random_f64();
+
+        let z = 1. + r2 * ((1. - radius_squared / distance_squared).sqrt() - 1.);
+        let phi = 2. * PI * r1;
+        let sin_theta = (1. - z * z).sqrt();
+
+        Onb::new(&direction).transform(&Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z))
+    }
 
-        Some(HitRecord::new(point, normal, t, r, self.material.clone()))
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
     }
 }
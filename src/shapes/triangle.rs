@@ -0,0 +1,180 @@
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    random_f64,
+    Ray,
+    Vec3,
+    material::Material,
+    INFINITY,
+};
+
+type Point3 = Vec3;
+
+pub struct Triangle {
+    /// Counter-clockwise winding when looking at the front face
+    pub vertices: [Point3; 3],
+    /// Per vertex normals interpolated over the face for smooth shading,
+    /// None uses the flat face normal
+    pub normals: Option<[Vec3; 3]>,
+    /// Ignores hits from behind the face
+    pub backface_culling: bool,
+    material: Rc<dyn Material>,
+}
+
+impl Triangle {
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Rc<dyn Material>) -> Triangle {
+        Triangle {
+            vertices: [a, b, c],
+            normals: None,
+            backface_culling: false,
+            material,
+        }
+    }
+
+    pub fn with_normals(
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        normals: [Vec3; 3],
+        material: Rc<dyn Material>,
+    ) -> Triangle {
+        Triangle {
+            vertices: [a, b, c],
+            normals: Some(normals),
+            backface_culling: false,
+            material,
+        }
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let (t, u, v) = intersect(&self.vertices, r, ray_tmin, ray_tmax, self.backface_culling)?;
+
+        Some(hit_record(&self.vertices, self.normals.as_ref(), r, t, u, v, self.material.clone()))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = self.vertices;
+
+        Aabb::surrounding(&Aabb::new(a, b), &Aabb::new(a, c))
+    }
+
+    /// Points are picked evenly over the face
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let r = Ray::new(*origin, *direction, time);
+        let Some((t, _, _)) = intersect(&self.vertices, &r, 0.001, INFINITY, self.backface_culling) else {
+            return 0.;
+        };
+
+        let [a, b, c] = self.vertices;
+        let cross = Vec3::cross(&(b - a), &(c - a));
+        let area = 0.5 * cross.length();
+
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = Vec3::dot(&cross.unit_vector(), &direction.unit_vector()).abs();
+
+        if area <= 0. || cosine <= 0. {
+            return 0.;
+        }
+
+        distance_squared / (cosine * area)
+    }
+
+    fn random(&self, origin: &Point3, _time: f64) -> Vec3 {
+        let [a, b, c] = self.vertices;
+
+        // square root keeps the points from bunching up at the first vertex
+        let su = random_f64().sqrt();
+        let r2 = random_f64();
+
+        a * (1. - su) + b * (su * (1. - r2)) + c * (su * r2) - *origin
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.material.is_emissive()
+    }
+}
+
+/// Möller–Trumbore intersection, returns t and the barycentric weights of
+/// the second and third vertices
+pub(crate) fn intersect(
+    vertices: &[Point3; 3],
+    r: &Ray,
+    ray_tmin: f64,
+    ray_tmax: f64,
+    backface_culling: bool,
+) -> Option<(f64, f64, f64)> {
+    let epsilon = 1e-12;
+
+    let [a, b, c] = *vertices;
+    let edge1 = b - a;
+    let edge2 = c - a;
+
+    let p = Vec3::cross(&r.direction, &edge2);
+    // positive when the ray comes at the front face
+    let det = Vec3::dot(&edge1, &p);
+
+    if backface_culling && det < epsilon {
+        return None;
+    }
+
+    // ray is parallel to the triangle's plane
+    if det.abs() < epsilon {
+        return None;
+    }
+
+    let inv_det = 1. / det;
+
+    let s = r.origin - a;
+    let u = Vec3::dot(&s, &p) * inv_det;
+    if !(0. ..=1.).contains(&u) {
+        return None;
+    }
+
+    let q = Vec3::cross(&s, &edge1);
+    let v = Vec3::dot(&r.direction, &q) * inv_det;
+    if v < 0. || u + v > 1. {
+        return None;
+    }
+
+    let t = Vec3::dot(&edge2, &q) * inv_det;
+    if t <= ray_tmin || ray_tmax <= t {
+        return None;
+    }
+
+    Some((t, u, v))
+}
+
+/// Hit record for an intersection found by intersect
+pub(crate) fn hit_record(
+    vertices: &[Point3; 3],
+    normals: Option<&[Vec3; 3]>,
+    r: &Ray,
+    t: f64,
+    u: f64,
+    v: f64,
+    material: Rc<dyn Material>,
+) -> HitRecord {
+    let [a, b, c] = *vertices;
+    let face_normal = Vec3::cross(&(b - a), &(c - a)).unit_vector();
+
+    // front face is decided by the real surface, not the shading normal
+    let mut rec = HitRecord::new(r.at(t), face_normal, t, r, material);
+    rec.barycentric = Some((u, v));
+    (rec.u, rec.v) = (u, v);
+
+    if let Some([na, nb, nc]) = normals {
+        let shading_normal = (*na * (1. - u - v) + *nb * u + *nc * v).unit_vector();
+
+        rec.normal = if rec.front_face {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+    }
+
+    rec
+}
@@ -0,0 +1,345 @@
+use std::sync::OnceLock;
+
+use crate::{
+    random_f64,
+    Color,
+    Vec3,
+};
+
+/// Shortest wavelength in nanometres that is sampled
+pub const LAMBDA_MIN: f64 = 380.;
+/// Longest wavelength in nanometres that is sampled
+pub const LAMBDA_MAX: f64 = 780.;
+
+/// Wavelength refractive indices are quoted at when nothing else is asked
+/// for, the yellow helium d line
+pub const REFERENCE_WAVELENGTH: f64 = 587.6;
+
+/// How the camera handles color along a path
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub enum ColorMode {
+    /// Every path carries all three channels at once
+    #[default]
+    Rgb,
+    /// Every path carries one random wavelength and adds its share to each
+    /// channel, so glass can bend each wavelength its own way. Noisier in
+    /// color than Rgb
+    SingleWavelength,
+    /// Every path carries a few wavelengths and works with spectra
+    /// throughout, rgb colors are turned into smooth spectra where they're
+    /// used. Interreflections between saturated colors come out right
+    Spectral,
+}
+
+/// Number of wavelengths a spectral path carries, one per channel of its
+/// colors
+pub const WAVELENGTH_COUNT: usize = 3;
+
+/// Wavelengths in nanometres carried by a spectral path. The first is the
+/// hero, the others are spread evenly after it through the visible range
+#[derive(Clone, Copy, Debug)]
+pub struct Wavelengths {
+    pub lambda: [f64; WAVELENGTH_COUNT],
+    /// False once a bounce depended on wavelength, only the hero follows the
+    /// path from then on
+    pub secondary: bool,
+}
+
+impl Wavelengths {
+    /// Random hero, each of the wavelengths is uniformly distributed
+    pub fn sample() -> Wavelengths {
+        let hero = sample_wavelength();
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+
+        Wavelengths {
+            lambda: std::array::from_fn(|i| {
+                LAMBDA_MIN + (hero - LAMBDA_MIN + range * i as f64 / WAVELENGTH_COUNT as f64) % range
+            }),
+            secondary: true,
+        }
+    }
+
+    /// Rgb of radiance given per wavelength
+    pub fn to_rgb(&self, radiance: &Color) -> Color {
+        let mut res = Color::default();
+        for (i, lambda) in self.lambda.iter().enumerate() {
+            res += wavelength_to_rgb(*lambda) * radiance[i];
+        }
+
+        res / WAVELENGTH_COUNT as f64
+    }
+}
+
+/// What the colors along a path stand for, carried by its rays
+#[derive(Clone, Copy, Default, Debug)]
+pub enum PathColor {
+    /// The three rgb channels
+    #[default]
+    Rgb,
+    /// Rgb colors, with refraction following this wavelength in nanometres.
+    /// The path's result is tinted by the wavelength's share of each channel
+    Single(f64),
+    /// Values at each of the wavelengths instead of rgb
+    Spectral(Wavelengths),
+}
+
+impl PathColor {
+    /// Wavelength refraction follows, the hero on spectral paths
+    pub fn wavelength(&self) -> Option<f64> {
+        match self {
+            PathColor::Rgb => None,
+            PathColor::Single(wavelength) => Some(*wavelength),
+            PathColor::Spectral(wavelengths) => Some(wavelengths.lambda[0]),
+        }
+    }
+
+    pub fn wavelengths(&self) -> Option<&Wavelengths> {
+        match self {
+            PathColor::Spectral(wavelengths) => Some(wavelengths),
+            _ => None,
+        }
+    }
+}
+
+/// Index of refraction, either fixed or changing with wavelength
+#[derive(Clone, Copy, Debug)]
+pub enum RefractiveIndex {
+    Constant(f64),
+    /// n = a + b / λ², with λ in micrometres
+    Cauchy { a: f64, b: f64 },
+    /// n² = 1 + Σ bᵢλ² / (λ² - cᵢ), with λ in micrometres
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl From<f64> for RefractiveIndex {
+    fn from(n: f64) -> Self {
+        RefractiveIndex::Constant(n)
+    }
+}
+
+impl RefractiveIndex {
+    pub fn is_constant(&self) -> bool {
+        matches!(self, RefractiveIndex::Constant(_))
+    }
+
+    /// Index at wavelength in nanometres, or at REFERENCE_WAVELENGTH for None
+    pub fn at(&self, wavelength: Option<f64>) -> f64 {
+        let micrometres = wavelength.unwrap_or(REFERENCE_WAVELENGTH) / 1000.;
+        let l2 = micrometres * micrometres;
+
+        match self {
+            RefractiveIndex::Constant(n) => *n,
+            RefractiveIndex::Cauchy { a, b } => a + b / l2,
+            RefractiveIndex::Sellmeier { b, c } => {
+                let n2 = 1. + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f64>();
+                n2.max(1.).sqrt()
+            }
+        }
+    }
+}
+
+/// Uniformly random wavelength in the visible range
+pub fn sample_wavelength() -> f64 {
+    LAMBDA_MIN + random_f64() * (LAMBDA_MAX - LAMBDA_MIN)
+}
+
+/// CIE 1931 2° color matching functions, from the multi-lobe gaussian fit by
+/// Wyman, Sloan and Shirley
+pub fn cie_xyz(wavelength: f64) -> Vec3 {
+    fn g(x: f64, mu: f64, sigma_low: f64, sigma_high: f64) -> f64 {
+        let t = (x - mu) / if x < mu { sigma_low } else { sigma_high };
+        (-0.5 * t * t).exp()
+    }
+
+    let l = wavelength;
+
+    Vec3::new(
+        1.056 * g(l, 599.8, 37.9, 31.0) + 0.362 * g(l, 442.0, 16.0, 26.7) - 0.065 * g(l, 501.1, 20.4, 26.2),
+        0.821 * g(l, 568.8, 46.9, 40.5) + 0.286 * g(l, 530.9, 16.3, 31.1),
+        1.217 * g(l, 437.0, 11.8, 36.0) + 0.681 * g(l, 459.0, 26.0, 13.8),
+    )
+}
+
+/// Linear sRGB with a D65 white point
+pub fn xyz_to_linear_srgb(xyz: &Vec3) -> Color {
+    let (x, y, z) = (*xyz.x(), *xyz.y(), *xyz.z());
+
+    Color::new(
+        3.2404542 * x - 1.5371385 * y - 0.4985314 * z,
+        -0.9692660 * x + 1.8760108 * y + 0.0415560 * z,
+        0.0556434 * x - 0.2040259 * y + 1.0572252 * z,
+    )
+}
+
+/// What one uniformly sampled wavelength adds to each channel. Averaged
+/// over the visible range it comes to (1, 1, 1), so a flat spectrum stays
+/// white. Some channels go negative for colors outside of sRGB
+pub fn wavelength_to_rgb(wavelength: f64) -> Color {
+    static MEAN: OnceLock<Color> = OnceLock::new();
+
+    let mean = MEAN.get_or_init(|| {
+        let steps = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+
+        let mut sum = Color::default();
+        for i in 0..steps {
+            sum += xyz_to_linear_srgb(&cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step));
+        }
+
+        sum / steps as f64
+    });
+
+    xyz_to_linear_srgb(&cie_xyz(wavelength)) / *mean
+}
+
+// Smits' basis spectra for turning rgb into spectra, ten bins from 380 to
+// 720 nanometres
+const SMITS_WHITE: [f64; 10] = [1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000];
+const SMITS_CYAN: [f64; 10] = [0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000];
+const SMITS_MAGENTA: [f64; 10] = [1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959];
+const SMITS_YELLOW: [f64; 10] = [0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840];
+const SMITS_RED: [f64; 10] = [0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149];
+const SMITS_GREEN: [f64; 10] = [0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025];
+const SMITS_BLUE: [f64; 10] = [1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496];
+
+/// Basis spectrum at wavelength, blended linearly between bin centers so
+/// the result is smooth
+fn smits_basis(table: &[f64; 10], wavelength: f64) -> f64 {
+    let bin_width = (720. - 380.) / 10.;
+    let x = ((wavelength - 380.) / bin_width - 0.5).clamp(0., 9.);
+    let i = (x.floor() as usize).min(8);
+    let frac = x - i as f64;
+
+    table[i] * (1. - frac) + table[i + 1] * frac
+}
+
+/// Value at wavelength of a smooth spectrum that looks like rgb, by Smits'
+/// method. Scales with rgb, so it works for lights as well as reflectances
+pub fn rgb_to_spectrum_value(rgb: &Color, wavelength: f64) -> f64 {
+    let (r, g, b) = (*rgb.x(), *rgb.y(), *rgb.z());
+    let basis = |table| smits_basis(table, wavelength);
+
+    if r <= g && r <= b {
+        r * basis(&SMITS_WHITE)
+            + if g <= b {
+                (g - r) * basis(&SMITS_CYAN) + (b - g) * basis(&SMITS_BLUE)
+            } else {
+                (b - r) * basis(&SMITS_CYAN) + (g - b) * basis(&SMITS_GREEN)
+            }
+    } else if g <= r && g <= b {
+        g * basis(&SMITS_WHITE)
+            + if r <= b {
+                (r - g) * basis(&SMITS_MAGENTA) + (b - r) * basis(&SMITS_BLUE)
+            } else {
+                (b - g) * basis(&SMITS_MAGENTA) + (r - b) * basis(&SMITS_RED)
+            }
+    } else {
+        b * basis(&SMITS_WHITE)
+            + if r <= g {
+                (r - b) * basis(&SMITS_YELLOW) + (g - r) * basis(&SMITS_GREEN)
+            } else {
+                (g - b) * basis(&SMITS_YELLOW) + (r - g) * basis(&SMITS_RED)
+            }
+    }
+}
+
+/// rgb's spectrum at each of the wavelengths, one per channel
+pub fn rgb_to_spectrum(rgb: &Color, wavelengths: &Wavelengths) -> Color {
+    let [a, b, c] = wavelengths.lambda.map(|lambda| rgb_to_spectrum_value(rgb, lambda));
+
+    Color::new(a, b, c)
+}
+
+/// Light given off by a hot object, by Planck's law. Scaled so its rgb has
+/// a luminance of intensity
+#[derive(Clone, Copy, Debug)]
+pub struct Blackbody {
+    /// In kelvin
+    pub temperature: f64,
+    scale: f64,
+}
+
+impl Blackbody {
+    /// Panics unless the temperature gives off visible light, see
+    /// is_visible
+    pub fn new(temperature: f64, intensity: f64) -> Blackbody {
+        assert!(temperature > 0., "blackbody temperature must be positive");
+
+        let luminance = Blackbody::unscaled_luminance(temperature);
+        assert!(
+            luminance.is_finite() && luminance > 0.,
+            "blackbody at {temperature}K gives off no visible light"
+        );
+
+        Blackbody {
+            temperature,
+            scale: intensity / luminance,
+        }
+    }
+
+    /// If an object at temperature in kelvin glows at all in the visible
+    /// range. Below a few tens of kelvin its light is too faint to measure
+    pub fn is_visible(temperature: f64) -> bool {
+        if temperature <= 0. {
+            return false;
+        }
+
+        let luminance = Blackbody::unscaled_luminance(temperature);
+
+        luminance.is_finite() && luminance > 0.
+    }
+
+    fn unscaled_luminance(temperature: f64) -> f64 {
+        luminance(&Blackbody { temperature, scale: 1. }.to_rgb())
+    }
+
+    /// Spectral radiance at wavelength in nanometres
+    pub fn value(&self, wavelength: f64) -> f64 {
+        const H: f64 = 6.62607015e-34;
+        const C: f64 = 299792458.;
+        const K: f64 = 1.380649e-23;
+
+        let l = wavelength * 1e-9;
+
+        self.scale * 2. * H * C * C / (l.powi(5) * ((H * C / (l * K * self.temperature)).exp() - 1.))
+    }
+
+    pub fn to_spectrum(&self, wavelengths: &Wavelengths) -> Color {
+        let [a, b, c] = wavelengths.lambda.map(|lambda| self.value(lambda));
+
+        Color::new(a, b, c)
+    }
+
+    /// The color the spectrum is seen as
+    pub fn to_rgb(&self) -> Color {
+        let steps = 1000;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+
+        let mut sum = Color::default();
+        for i in 0..steps {
+            let lambda = LAMBDA_MIN + (i as f64 + 0.5) * step;
+            sum += wavelength_to_rgb(lambda) * self.value(lambda);
+        }
+
+        sum / steps as f64
+    }
+
+    /// to_rgb brought into the sRGB gamut, for paths that carry rgb. Below
+    /// about 1900K the light is redder than sRGB can show and to_rgb goes
+    /// negative in blue, that is clipped while keeping the luminance
+    pub fn to_rgb_clipped(&self) -> Color {
+        let rgb = self.to_rgb();
+        let clipped = Color::new(rgb.x().max(0.), rgb.y().max(0.), rgb.z().max(0.));
+
+        let clipped_luminance = luminance(&clipped);
+        if clipped_luminance <= 0. {
+            return clipped;
+        }
+
+        clipped * (luminance(&rgb) / clipped_luminance)
+    }
+}
+
+fn luminance(rgb: &Color) -> f64 {
+    0.2126 * rgb.x() + 0.7152 * rgb.y() + 0.0722 * rgb.z()
+}
@@ -0,0 +1,200 @@
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::{
+    output::ImageError,
+    perlin::Perlin,
+    Color,
+    Vec3,
+};
+
+type Point3 = Vec3;
+
+/// Color that varies over a surface
+pub trait Texture {
+    /// u and v are the surface coordinates of the hit, p its position
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color;
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+/// Alternates between two textures in cubes of scale size
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Rc<dyn Texture>,
+    odd: Rc<dyn Texture>,
+}
+
+/// Image stretched over the surface's u and v, (0, 0) is the bottom left
+/// of the image
+pub struct ImageTexture {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+/// Procedural texture blending between two colors by a noise pattern
+pub struct NoiseTexture {
+    noise: Perlin,
+    pub pattern: NoisePattern,
+    /// Frequency of the noise, larger values give finer detail
+    pub scale: f64,
+    /// Color where the pattern is 0
+    pub low: Color,
+    /// Color where the pattern is 1
+    pub high: Color,
+}
+
+pub enum NoisePattern {
+    /// Plain Perlin noise
+    Perlin,
+    /// Octaves of the noise's magnitude, gives a rougher look
+    Turbulence { depth: u32 },
+    /// Fractional Brownian motion, octaves halve in weight and double in
+    /// frequency
+    Fbm { octaves: u32 },
+    /// Stripes along z bent by turbulence
+    Marble { depth: u32 },
+    /// Rings around the y axis bent by turbulence
+    Wood { depth: u32 },
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> SolidColor {
+        SolidColor {
+            albedo,
+        }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Rc<dyn Texture>, odd: Rc<dyn Texture>) -> CheckerTexture {
+        CheckerTexture {
+            inv_scale: 1. / scale,
+            even,
+            odd,
+        }
+    }
+
+    pub fn from_colors(scale: f64, even: Color, odd: Color) -> CheckerTexture {
+        CheckerTexture::new(scale, Rc::from(SolidColor::new(even)), Rc::from(SolidColor::new(odd)))
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, p: &Point3) -> Color {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z).rem_euclid(2) == 0 {
+            self.even.value(u, v, p)
+        } else {
+            self.odd.value(u, v, p)
+        }
+    }
+}
+
+impl ImageTexture {
+    /// Reads a png or jpeg file
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ImageTexture, ImageError> {
+        let image = image::open(path)
+            .map_err(|e| match e {
+                image::ImageError::IoError(e) => ImageError::Io(e),
+                e => ImageError::Decoding(e.to_string()),
+            })?
+            .into_rgb32f();
+
+        let (width, height) = image.dimensions();
+
+        // files are stored with the same gamma 2 the renderer writes, undo it
+        // to get back linear colors
+        let pixels = image
+            .pixels()
+            .map(|pixel| Color::new(pixel[0] * pixel[0], pixel[1] * pixel[1], pixel[2] * pixel[2]))
+            .collect();
+
+        Ok(ImageTexture::from_pixels(width as usize, height as usize, pixels))
+    }
+
+    /// Pixels are linear colors in row major order starting from the top left
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> ImageTexture {
+        assert_eq!(pixels.len(), width * height, "pixel count doesn't match the size");
+
+        ImageTexture {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Point3) -> Color {
+        // no image shows up as cyan so it is easy to spot
+        if self.pixels.is_empty() {
+            return Color::new(0, 1, 1);
+        }
+
+        let u = u.clamp(0., 1.);
+        // image rows go down while v goes up
+        let v = 1. - v.clamp(0., 1.);
+
+        let x = ((u * self.width as f64) as usize).min(self.width - 1);
+        let y = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[y * self.width + x]
+    }
+}
+
+impl NoiseTexture {
+    /// Blends from black to white, noise from the same seed is always the
+    /// same
+    pub fn new(seed: u64, pattern: NoisePattern, scale: f64) -> NoiseTexture {
+        NoiseTexture {
+            noise: Perlin::new(seed),
+            pattern,
+            scale,
+            low: Color::new(0, 0, 0),
+            high: Color::new(1, 1, 1),
+        }
+    }
+
+    /// Value of the pattern at p, in [0, 1]
+    fn pattern_value(&self, p: &Point3) -> f64 {
+        let scaled = *p * self.scale;
+
+        let value = match self.pattern {
+            NoisePattern::Perlin => 0.5 * (1. + self.noise.noise(&scaled)),
+            NoisePattern::Turbulence { depth } => self.noise.turbulence(&scaled, depth),
+            NoisePattern::Fbm { octaves } => 0.5 * (1. + self.noise.fbm(&scaled, octaves, 2., 0.5)),
+            NoisePattern::Marble { depth } => {
+                0.5 * (1. + (scaled.z() + 10. * self.noise.turbulence(p, depth)).sin())
+            }
+            NoisePattern::Wood { depth } => {
+                let radius = (scaled.x() * scaled.x() + scaled.z() * scaled.z()).sqrt();
+                let rings = radius + 0.5 * self.noise.turbulence(&scaled, depth);
+
+                rings - rings.floor()
+            }
+        };
+
+        value.clamp(0., 1.)
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, p: &Point3) -> Color {
+        let t = self.pattern_value(p);
+
+        self.low * (1. - t) + self.high * t
+    }
+}
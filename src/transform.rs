@@ -0,0 +1,305 @@
+use std::rc::Rc;
+
+use crate::{
+    aabb::Aabb,
+    degrees_to_radians,
+    hit::{HitRecord, Hittable},
+    motion::Motion,
+    Color,
+    Ray,
+    Vec3,
+};
+
+type Point3 = Vec3;
+
+type Matrix = [[f64; 4]; 4];
+
+const IDENTITY: Matrix = [
+    [1., 0., 0., 0.],
+    [0., 1., 0., 0.],
+    [0., 0., 1., 0.],
+    [0., 0., 0., 1.],
+];
+
+/// Affine 4x4 transform, kept together with its inverse
+#[derive(Clone, Copy)]
+pub struct Transform {
+    m: Matrix,
+    inv: Matrix,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            m: IDENTITY,
+            inv: IDENTITY,
+        }
+    }
+
+    pub fn translate(offset: Vec3) -> Transform {
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+
+        for i in 0..3 {
+            m[i][3] = offset[i];
+            inv[i][3] = -offset[i];
+        }
+
+        Transform {
+            m,
+            inv,
+        }
+    }
+
+    /// Panics if any factor is zero, as the transform couldn't be undone
+    pub fn scale(factors: Vec3) -> Transform {
+        assert!((0..3).all(|i| factors[i] != 0.), "scale factors can't be zero");
+
+        let mut m = IDENTITY;
+        let mut inv = IDENTITY;
+
+        for i in 0..3 {
+            m[i][i] = factors[i];
+            inv[i][i] = 1. / factors[i];
+        }
+
+        Transform {
+            m,
+            inv,
+        }
+    }
+
+    pub fn rotate_x(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(1, 0, 0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0, 1, 0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Transform {
+        Transform::rotate(Vec3::new(0, 0, 1), degrees)
+    }
+
+    /// Counter-clockwise rotation about axis when looking down it towards
+    /// the origin
+    pub fn rotate(axis: Vec3, degrees: f64) -> Transform {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+
+        // rodrigues' rotation formula
+        let mut m = IDENTITY;
+        for i in 0..3 {
+            for j in 0..3 {
+                let identity = if i == j { 1. } else { 0. };
+                m[i][j] = a[i] * a[j] * (1. - cos) + identity * cos;
+            }
+        }
+
+        m[0][1] -= a[2] * sin;
+        m[0][2] += a[1] * sin;
+        m[1][0] += a[2] * sin;
+        m[1][2] -= a[0] * sin;
+        m[2][0] -= a[1] * sin;
+        m[2][1] += a[0] * sin;
+
+        // rotations are orthogonal, the inverse is the transpose
+        Transform {
+            m,
+            inv: transpose(&m),
+        }
+    }
+
+    /// Applies self first and then next
+    pub fn then(&self, next: &Transform) -> Transform {
+        Transform {
+            m: multiply(&next.m, &self.m),
+            inv: multiply(&self.inv, &next.inv),
+        }
+    }
+
+    pub fn inverse(&self) -> Transform {
+        Transform {
+            m: self.inv,
+            inv: self.m,
+        }
+    }
+
+    pub fn point(&self, p: &Point3) -> Point3 {
+        apply(&self.m, p, 1.)
+    }
+
+    /// Directions and offsets, which translation doesn't affect
+    pub fn vector(&self, v: &Vec3) -> Vec3 {
+        apply(&self.m, v, 0.)
+    }
+
+    /// Surface normals stay perpendicular to the surface by using the
+    /// inverse transpose, the result isn't unit length
+    pub fn normal(&self, n: &Vec3) -> Vec3 {
+        apply(&transpose(&self.inv), n, 0.)
+    }
+
+    /// Box containing the transformed box
+    pub fn bounding_box(&self, bbox: &Aabb) -> Aabb {
+        if bbox.is_empty() {
+            return *bbox;
+        }
+
+        (0..8).fold(Aabb::empty(), |res, corner| {
+            let p = Point3::new(
+                if corner & 1 == 0 { *bbox.min.x() } else { *bbox.max.x() },
+                if corner & 2 == 0 { *bbox.min.y() } else { *bbox.max.y() },
+                if corner & 4 == 0 { *bbox.min.z() } else { *bbox.max.z() },
+            );
+            let p = self.point(&p);
+
+            Aabb::surrounding(&res, &Aabb { min: p, max: p })
+        })
+    }
+
+    /// Determinant of the linear part, how much volumes are scaled by
+    fn determinant(&self) -> f64 {
+        let m = &self.m;
+
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+}
+
+/// Places an object in the world through a transform, so one object can be
+/// shared by many instances
+pub struct Instance {
+    object: Rc<dyn Hittable>,
+    transform: Transform,
+    /// Moves the transformed object over time
+    offset: Motion,
+    bbox: Aabb,
+}
+
+impl Instance {
+    pub fn new(object: Rc<dyn Hittable>, transform: Transform) -> Instance {
+        Instance::moving(object, transform, Motion::fixed(Vec3::default()))
+    }
+
+    /// After the transform the object is moved by offset, which changes over
+    /// time for motion blur
+    pub fn moving(object: Rc<dyn Hittable>, transform: Transform, offset: Motion) -> Instance {
+        let placed = transform.bounding_box(&object.bounding_box());
+
+        let bbox = offset
+            .positions()
+            .map(|offset| Transform::translate(offset).bounding_box(&placed))
+            .fold(Aabb::empty(), |bbox, key_box| Aabb::surrounding(&bbox, &key_box));
+
+        Instance {
+            object,
+            transform,
+            offset,
+            bbox,
+        }
+    }
+
+    /// Origin moved into the object's space at time
+    fn object_origin(&self, origin: &Point3, time: f64) -> Point3 {
+        self.transform.inverse().point(&(*origin - self.offset.at(time)))
+    }
+
+    /// r moved into the object's space
+    fn object_ray(&self, r: &Ray) -> Ray {
+        // the direction isn't normalised, so t is the same in both spaces
+        Ray::new(self.object_origin(&r.origin, r.time), self.transform.inverse().vector(&r.direction), r.time)
+            .with_path_of(r)
+    }
+
+    pub fn transform(&self) -> &Transform {
+        &self.transform
+    }
+}
+
+impl Hittable for Instance {
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let mut rec = self.object.hit(&self.object_ray(r), ray_tmin, ray_tmax)?;
+
+        rec.point = self.transform.point(&rec.point) + self.offset.at(r.time);
+        rec.normal = self.transform.normal(&rec.normal).unit_vector();
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let inverse = self.transform.inverse();
+        let object_direction = inverse.vector(direction).unit_vector();
+
+        let pdf = self.object.pdf_value(&self.object_origin(origin, time), &object_direction, time);
+
+        // solid angles stretch with the transform, by |det| / |A u|^3 for a
+        // unit direction u
+        let stretch = self.transform.vector(&object_direction).length();
+
+        pdf * stretch.powi(3) / self.transform.determinant().abs()
+    }
+
+    fn random(&self, origin: &Point3, time: f64) -> Vec3 {
+        let object_origin = self.object_origin(origin, time);
+
+        self.transform.vector(&self.object.random(&object_origin, time))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.object.is_emissive()
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        self.object.transmittance(&self.object_ray(r), ray_tmin, ray_tmax)
+    }
+
+    fn transmittance_correction(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        self.object.transmittance_correction(&self.object_ray(r), ray_tmin, ray_tmax)
+    }
+
+    fn has_chromatic_volume(&self) -> bool {
+        self.object.has_chromatic_volume()
+    }
+}
+
+fn apply(m: &Matrix, v: &Vec3, w: f64) -> Vec3 {
+    let row = |i: usize| m[i][0] * v[0] + m[i][1] * v[1] + m[i][2] * v[2] + m[i][3] * w;
+
+    Vec3::new(row(0), row(1), row(2))
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut res = [[0.; 4]; 4];
+
+    for (i, row) in res.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+
+    res
+}
+
+fn transpose(m: &Matrix) -> Matrix {
+    let mut res = [[0.; 4]; 4];
+
+    for (i, row) in res.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = m[j][i];
+        }
+    }
+
+    res
+}
@@ -1,7 +1,7 @@
 use std::ops;
 
 use crate::{
-    clamp, random_f64, random_range_f64, surrounds
+    clamp, random_f64, random_range_f64, surrounds, PI
 };
 
 #[derive(Copy)]
@@ -103,6 +103,20 @@ impl Vec3 {
         }
     }
 
+    /// Random direction around +z, denser towards z by the cosine of the
+    /// angle from it
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1 = random_f64();
+        let r2 = random_f64();
+
+        let phi = 2. * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1. - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
     pub fn random_unit_vec_on_hemisphere(surface_normal: &Vec3) -> Vec3 {
         let on_unit_sphere = Vec3::random_unit_vec();
 
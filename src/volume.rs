@@ -0,0 +1,587 @@
+use std::fmt;
+use std::fs;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    aabb::Aabb,
+    hit::{HitRecord, Hittable},
+    material::{Material, ScatterKind, ScatterRecord},
+    medium::boundary_segment,
+    pdf::SpherePdf,
+    perlin::Perlin,
+    random_f64,
+    spectrum::{self, Wavelengths},
+    Color,
+    Ray,
+    Vec3,
+    PI,
+};
+
+type Point3 = Vec3;
+
+/// First bytes of a dense grid file
+const GRID_MAGIC: &[u8; 8] = b"DENSGRID";
+
+#[derive(Debug)]
+pub enum VolumeError {
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    Format {
+        path: PathBuf,
+        message: String,
+    },
+}
+
+impl fmt::Display for VolumeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VolumeError::Io { path, source } => write!(f, "{}: {}", path.display(), source),
+            VolumeError::Format { path, message } => write!(f, "{}: {}", path.display(), message),
+        }
+    }
+}
+
+impl std::error::Error for VolumeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            VolumeError::Io { source, .. } => Some(source),
+            VolumeError::Format { .. } => None,
+        }
+    }
+}
+
+/// How much matter there is at each point of a volume
+pub trait DensityField {
+    /// Never negative
+    fn density(&self, p: &Point3) -> f64;
+
+    /// Upper bound of density anywhere, the tighter the faster tracking is
+    fn max_density(&self) -> f64;
+}
+
+/// Densities sampled on a regular grid of voxels stretched over bounds,
+/// blended trilinearly between voxel centers. Zero outside bounds
+pub struct DensityGrid {
+    resolution: [usize; 3],
+    values: Vec<f32>,
+    max_value: f64,
+    /// Defaults to the unit cube
+    pub bounds: Aabb,
+}
+
+impl DensityGrid {
+    /// Values are ordered with x changing fastest, then y, then z
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> DensityGrid {
+        assert_eq!(
+            resolution.iter().product::<usize>(),
+            values.len(),
+            "grid needs one value per voxel"
+        );
+        assert!(values.iter().all(|v| *v >= 0.), "grid densities can't be negative");
+
+        let max_value = values.iter().fold(0., |acc: f64, v| acc.max(*v as f64));
+
+        DensityGrid {
+            resolution,
+            values,
+            max_value,
+            bounds: Aabb::new(Point3::new(0, 0, 0), Point3::new(1, 1, 1)),
+        }
+    }
+
+    /// Reads a dense grid file: the 8 bytes "DENSGRID", the x, y and z
+    /// resolution as little endian u32s, then one little endian f32 per
+    /// voxel in the order new expects
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<DensityGrid, VolumeError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|source| VolumeError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        let format_error = |message: &str| VolumeError::Format {
+            path: path.to_path_buf(),
+            message: String::from(message),
+        };
+
+        if bytes.len() < 20 || &bytes[..8] != GRID_MAGIC {
+            return Err(format_error("not a dense grid file"));
+        }
+
+        let mut resolution = [0; 3];
+        for (axis, n) in resolution.iter_mut().enumerate() {
+            let start = 8 + axis * 4;
+            *n = u32::from_le_bytes(bytes[start..start + 4].try_into().unwrap()) as usize;
+        }
+
+        let voxels = resolution.iter().try_fold(1usize, |acc, n| acc.checked_mul(*n));
+        if voxels.is_none_or(|voxels| voxels == 0 || Some(bytes.len() - 20) != voxels.checked_mul(4)) {
+            return Err(format_error("grid data doesn't match its resolution"));
+        }
+
+        let values: Vec<f32> = bytes[20..]
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        if values.iter().any(|v| !v.is_finite() || *v < 0.) {
+            return Err(format_error("grid densities must be finite and not negative"));
+        }
+
+        Ok(DensityGrid::new(resolution, values))
+    }
+
+    pub fn resolution(&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    fn value(&self, i: usize, j: usize, k: usize) -> f64 {
+        let [nx, ny, _] = self.resolution;
+
+        self.values[i + nx * (j + ny * k)] as f64
+    }
+}
+
+impl DensityField for DensityGrid {
+    fn density(&self, p: &Point3) -> f64 {
+        let mut lower = [0; 3];
+        let mut frac = [0.; 3];
+
+        for axis in 0..3 {
+            let extent = self.bounds.max[axis] - self.bounds.min[axis];
+            let relative = (p[axis] - self.bounds.min[axis]) / extent;
+
+            if !(0. ..=1.).contains(&relative) {
+                return 0.;
+            }
+
+            // voxel values sit at the centers of their cells
+            let n = self.resolution[axis];
+            let x = (relative * n as f64 - 0.5).clamp(0., (n - 1) as f64);
+            lower[axis] = (x.floor() as usize).min(n.saturating_sub(2));
+            frac[axis] = x - lower[axis] as f64;
+        }
+
+        let mut accum = 0.;
+
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let weight = (if di == 1 { frac[0] } else { 1. - frac[0] })
+                        * (if dj == 1 { frac[1] } else { 1. - frac[1] })
+                        * (if dk == 1 { frac[2] } else { 1. - frac[2] });
+
+                    if weight > 0. {
+                        accum += weight * self.value(lower[0] + di, lower[1] + dj, lower[2] + dk);
+                    }
+                }
+            }
+        }
+
+        accum
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_value
+    }
+}
+
+/// Billowing density from fractional Brownian motion noise, between 0 and 1
+pub struct NoiseField {
+    noise: Perlin,
+    /// Frequency of the noise, larger values give smaller puffs
+    pub scale: f64,
+    pub octaves: u32,
+    /// Noise values below this are empty space, raise it for sparser clouds
+    pub threshold: f64,
+}
+
+impl NoiseField {
+    /// Noise from the same seed is always the same
+    pub fn new(seed: u64, scale: f64) -> NoiseField {
+        NoiseField {
+            noise: Perlin::new(seed),
+            scale,
+            octaves: 5,
+            threshold: 0.,
+        }
+    }
+}
+
+impl DensityField for NoiseField {
+    fn density(&self, p: &Point3) -> f64 {
+        let value = self.noise.fbm(&(*p * self.scale), self.octaves, 2., 0.5);
+
+        (value - self.threshold).clamp(0., 1.)
+    }
+
+    fn max_density(&self) -> f64 {
+        1.
+    }
+}
+
+/// Volume whose density varies from point to point, like clouds or
+/// explosions. Absorption, scattering and emission are per unit density, so
+/// each channel can be thicker or glow differently. Scattering is even in
+/// every direction and emission is the radiance of the absorbing matter.
+/// The boundary must be convex, density outside it is ignored
+pub struct HeterogeneousMedium {
+    boundary: Rc<dyn Hittable>,
+    field: Rc<dyn DensityField>,
+    pub absorption: Color,
+    pub scattering: Color,
+    pub emission: Color,
+}
+
+impl HeterogeneousMedium {
+    /// White, purely scattering matter
+    pub fn new(boundary: Rc<dyn Hittable>, field: Rc<dyn DensityField>) -> HeterogeneousMedium {
+        HeterogeneousMedium {
+            boundary,
+            field,
+            absorption: Color::new(0, 0, 0),
+            scattering: Color::new(1, 1, 1),
+            emission: Color::new(0, 0, 0),
+        }
+    }
+
+    /// Absorption, scattering and emission as r sees them, per wavelength on
+    /// spectral paths
+    fn path_coefficients(&self, r: &Ray) -> (Color, Color, Color) {
+        (
+            path_coefficient(&self.absorption, r),
+            path_coefficient(&self.scattering, r),
+            path_coefficient(&self.emission, r),
+        )
+    }
+
+    /// Ratio tracking estimate of e^(-coefficient * ∫ density) per channel
+    /// from t_enter to t_exit along r
+    fn ratio_tracking(&self, r: &Ray, t_enter: f64, t_exit: f64, coefficient: &Color) -> Color {
+        let majorant = self.field.max_density() * max_component(coefficient);
+
+        let mut res = Color::new(1, 1, 1);
+
+        if majorant <= 0. {
+            return res;
+        }
+
+        let ray_length = r.direction.length();
+        let mut t = t_enter;
+
+        loop {
+            t -= (1. - random_f64()).ln() / (majorant * ray_length);
+
+            if t >= t_exit || res.length_squared() <= 0. {
+                return res;
+            }
+
+            let density = self.field.density(&r.at(t));
+            res *= Color::new(1, 1, 1) - *coefficient * (density / majorant);
+        }
+    }
+
+    /// Delta tracking from t_enter up to t_end for one channel picked by
+    /// rng, keeping how likely every channel was to pass the same null
+    /// collisions so the channels can be weighed against each other
+    fn track<R: Rng>(&self, rng: &mut R, r: &Ray, t_enter: f64, t_end: f64, extinction: &Color) -> Tracking {
+        let majorant = self.field.max_density() * max_component(extinction);
+        let mut passed = Color::new(1, 1, 1);
+
+        if majorant <= 0. {
+            return Tracking::Passed(passed);
+        }
+
+        let hero = rng.gen_range(0..3);
+        let ray_length = r.direction.length();
+        let mut t = t_enter;
+
+        loop {
+            t -= (1. - rng.gen::<f64>()).ln() / (majorant * ray_length);
+
+            if t > t_end {
+                return Tracking::Passed(passed);
+            }
+
+            let point = r.at(t);
+            let density = self.field.density(&point);
+            let sigma_t = *extinction * density;
+
+            if rng.gen::<f64>() * majorant < sigma_t[hero] {
+                return Tracking::Collision {
+                    t,
+                    point,
+                    density,
+                    passed,
+                    sigma_t,
+                };
+            }
+
+            passed *= Color::new(1, 1, 1) - sigma_t / majorant;
+        }
+    }
+}
+
+/// How tracking along a ray ended, passed holds each channel's chance of
+/// getting through the null collisions on the way
+enum Tracking {
+    Collision {
+        t: f64,
+        point: Point3,
+        density: f64,
+        passed: Color,
+        sigma_t: Color,
+    },
+    Passed(Color),
+}
+
+impl Hittable for HeterogeneousMedium {
+    // where the channels thin out differently a channel is picked at random
+    // to track, and each channel's weight is balanced against how likely
+    // every channel was to make the same collisions. That keeps the weights
+    // at most 3, transmittance_correction weighs rays that get through
+    fn hit(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+        let (t_enter, t_exit) = boundary_segment(self.boundary.as_ref(), r, ray_tmin, ray_tmax)?;
+
+        let (absorption, scattering, emission) = self.path_coefficients(r);
+        let extinction = absorption + scattering;
+
+        let tracking = if self.has_chromatic_volume() {
+            self.track(&mut ray_rng(r), r, t_enter, t_exit, &extinction)
+        } else {
+            self.track(&mut rand::thread_rng(), r, t_enter, t_exit, &extinction)
+        };
+
+        let Tracking::Collision { t, point, density, passed, sigma_t } = tracking else {
+            return None;
+        };
+
+        // chance of these collisions averaged over the channel picked
+        let balance = mean(&(passed * sigma_t));
+
+        let event = VolumeEvent {
+            attenuation: passed * scattering * (density / balance),
+            emission: passed * absorption * emission * (density / balance),
+        };
+
+        let mut rec = HitRecord::new(point, Vec3::new(1, 0, 0), t, r, Rc::from(event));
+        rec.normal = Vec3::new(1, 0, 0);
+        rec.front_face = true;
+
+        Some(rec)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+
+    fn transmittance(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        let (absorption, scattering, _) = self.path_coefficients(r);
+
+        match boundary_segment(self.boundary.as_ref(), r, ray_tmin, ray_tmax) {
+            Some((t_enter, t_exit)) => self.ratio_tracking(r, t_enter, t_exit, &(absorption + scattering)),
+            None => Color::new(1, 1, 1),
+        }
+    }
+
+    // replays the tracking hit did for r, the collision there already
+    // carries its weight
+    fn transmittance_correction(&self, r: &Ray, ray_tmin: f64, ray_tmax: f64) -> Color {
+        if !self.has_chromatic_volume() {
+            return Color::new(1, 1, 1);
+        }
+
+        let Some((t_enter, t_end)) = boundary_segment(self.boundary.as_ref(), r, ray_tmin, ray_tmax) else {
+            return Color::new(1, 1, 1);
+        };
+
+        let (absorption, scattering, _) = self.path_coefficients(r);
+
+        match self.track(&mut ray_rng(r), r, t_enter, t_end, &(absorption + scattering)) {
+            Tracking::Passed(passed) => passed / mean(&passed),
+            Tracking::Collision { .. } => Color::new(1, 1, 1),
+        }
+    }
+
+    fn has_chromatic_volume(&self) -> bool {
+        // spectral paths turn each into a spectrum on its own, and those
+        // don't add up like the colors do
+        !is_grey(&self.absorption) || !is_grey(&self.scattering)
+    }
+}
+
+/// Real collision inside a heterogeneous medium. Its colors are already at
+/// the path's wavelengths on spectral paths
+struct VolumeEvent {
+    attenuation: Color,
+    emission: Color,
+}
+
+impl Material for VolumeEvent {
+    fn scatter(&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.attenuation,
+            kind: ScatterKind::Diffuse(Box::from(SpherePdf)),
+        })
+    }
+
+    fn emitted(&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        self.emission
+    }
+
+    fn emitted_spectrum(&self, _r_in: &Ray, _rec: &HitRecord, _wavelengths: &Wavelengths) -> Color {
+        self.emission
+    }
+
+    fn spectral_attenuation(&self) -> bool {
+        true
+    }
+
+    fn scattering_pdf(&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f64 {
+        1. / (4. * PI)
+    }
+}
+
+fn max_component(c: &Color) -> f64 {
+    c.x().max(*c.y()).max(*c.z())
+}
+
+fn mean(c: &Color) -> f64 {
+    (c.x() + c.y() + c.z()) / 3.
+}
+
+/// Random numbers that come out the same every time r is traced, so hit
+/// and transmittance_correction see the same collisions
+fn ray_rng(r: &Ray) -> StdRng {
+    let mut hasher = DefaultHasher::new();
+    for i in 0..3 {
+        r.origin[i].to_bits().hash(&mut hasher);
+        r.direction[i].to_bits().hash(&mut hasher);
+    }
+    r.time.to_bits().hash(&mut hasher);
+
+    StdRng::seed_from_u64(hasher.finish())
+}
+
+fn is_grey(c: &Color) -> bool {
+    c.x() == c.y() && c.x() == c.z()
+}
+
+/// c per wavelength on a spectral path. Grey stays exactly grey, so grey
+/// volumes need no correction for their channels
+fn path_coefficient(c: &Color, r: &Ray) -> Color {
+    match r.color.wavelengths() {
+        Some(wavelengths) if !is_grey(c) => spectrum::rgb_to_spectrum(c, wavelengths),
+        _ => *c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn grid_bytes(resolution: [u32; 3], values: &[f32]) -> Vec<u8> {
+        let mut bytes = GRID_MAGIC.to_vec();
+        for n in resolution {
+            bytes.extend_from_slice(&n.to_le_bytes());
+        }
+        for v in values {
+            bytes.extend_from_slice(&v.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    fn load_bytes(test: &str, bytes: &[u8]) -> Result<DensityGrid, VolumeError> {
+        let path = std::env::temp_dir().join(format!("grid-test-{}-{}.grid", process::id(), test));
+        fs::write(&path, bytes).unwrap();
+
+        DensityGrid::load(path)
+    }
+
+    fn format_message(result: Result<DensityGrid, VolumeError>) -> String {
+        match result {
+            Err(VolumeError::Format { message, .. }) => message,
+            Err(e) => panic!("expected a format error, got '{}'", e),
+            Ok(_) => panic!("expected a format error"),
+        }
+    }
+
+    #[test]
+    fn loads_values_in_x_fastest_order() {
+        let values: Vec<f32> = (0..12).map(|i| i as f32).collect();
+        let grid = load_bytes("valid", &grid_bytes([2, 3, 2], &values)).unwrap();
+
+        assert_eq!(grid.resolution(), [2, 3, 2]);
+        assert_eq!(grid.max_density(), 11.);
+
+        // voxel (i, j, k) sits at the center of its cell in the unit cube
+        for k in 0..2 {
+            for j in 0..3 {
+                for i in 0..2 {
+                    let center = Point3::new((i as f64 + 0.5) / 2., (j as f64 + 0.5) / 3., (k as f64 + 0.5) / 2.);
+                    let expected = (i + 2 * (j + 3 * k)) as f64;
+
+                    assert!((grid.density(&center) - expected).abs() < 1e-9, "voxel {} {} {}", i, j, k);
+                }
+            }
+        }
+
+        // halfway between the first two voxels along x
+        assert!((grid.density(&Point3::new(0.5, 0.5 / 3., 0.25)) - 0.5).abs() < 1e-9);
+        assert_eq!(grid.density(&Point3::new(1.5, 0.5, 0.5)), 0.);
+    }
+
+    #[test]
+    fn bad_headers_are_not_grids() {
+        let mut wrong_magic = grid_bytes([1, 1, 1], &[1.]);
+        wrong_magic[0] = b'X';
+
+        assert_eq!(format_message(load_bytes("magic", &wrong_magic)), "not a dense grid file");
+        assert_eq!(format_message(load_bytes("short-header", &grid_bytes([1, 1, 1], &[])[..16])), "not a dense grid file");
+        assert_eq!(format_message(load_bytes("empty", &[])), "not a dense grid file");
+    }
+
+    #[test]
+    fn data_must_match_resolution() {
+        let cases: [(&str, Vec<u8>); 5] = [
+            ("too-few", grid_bytes([2, 2, 2], &[1.; 7])),
+            ("too-many", grid_bytes([2, 2, 2], &[1.; 9])),
+            ("truncated", grid_bytes([2, 2, 2], &[1.; 8])[..50].to_vec()),
+            ("no-voxels", grid_bytes([0, 2, 2], &[])),
+            ("overflow", grid_bytes([u32::MAX, u32::MAX, u32::MAX], &[1.])),
+        ];
+
+        for (test, bytes) in &cases {
+            assert_eq!(format_message(load_bytes(test, bytes)), "grid data doesn't match its resolution", "{}", test);
+        }
+    }
+
+    #[test]
+    fn densities_must_be_finite_and_not_negative() {
+        for (test, value) in [("negative", -1.), ("nan", f32::NAN), ("infinite", f32::INFINITY)] {
+            let bytes = grid_bytes([2, 1, 1], &[1., value]);
+
+            assert_eq!(format_message(load_bytes(test, &bytes)), "grid densities must be finite and not negative", "{}", test);
+        }
+    }
+
+    #[test]
+    fn missing_file_is_an_io_error() {
+        let path = std::env::temp_dir().join(format!("grid-test-{}-absent.grid", process::id()));
+
+        match DensityGrid::load(&path) {
+            Err(VolumeError::Io { path: error_path, .. }) => assert_eq!(error_path, path),
+            _ => panic!("expected an io error"),
+        }
+    }
+}